@@ -0,0 +1,54 @@
+# tart shell integration for bash
+#
+# Marks prompts, commands and their exit status with OSC 133 and reports the working
+# directory with OSC 7. Source it at the end of ~/.bashrc, or let tart load it for you by
+# turning on `shell_integration`. Before bash 4.4 it takes over the DEBUG trap.
+
+[[ $- == *i* ]] || return 0
+[[ -n $__tart_integrated ]] && return 0
+__tart_integrated=1
+__tart_at_prompt=
+__tart_running=
+
+# Before bash 4.4 there is no PS0, so a DEBUG trap stands in for it
+__tart_preexec() {
+    # The DEBUG trap also fires for PROMPT_COMMAND itself and for completions
+    [[ -z $__tart_at_prompt || -n $COMP_LINE ]] && return
+    [[ $BASH_COMMAND == __tart_prompt_start* ]] && return
+    __tart_at_prompt=
+    __tart_running=1
+    printf '\e]133;C\a'
+}
+
+__tart_prompt_start() {
+    local code=$?
+    [[ -n $__tart_running ]] && printf '\e]133;D;%s\a' "$code"
+    __tart_at_prompt=
+    __tart_running=
+    printf '\e]7;file://%s%s\a' "$HOSTNAME" "$PWD"
+    printf '\e]133;A\a'
+    return $code
+}
+
+# PS0 is shown once a command has been read, never for an empty line. The subscript
+# marks the command as running without a subshell.
+__tart_ps0='${__tart_ps0_hook[__tart_running=1]}\e]133;C\a'
+
+__tart_prompt_end() {
+    # Prompt frameworks rebuild PS1 on every prompt, so check it each time
+    [[ $PS1 == *'\e]133;B\a'* ]] || PS1+='\[\e]133;B\a\]'
+    if (( BASH_VERSINFO[0] > 4 || (BASH_VERSINFO[0] == 4 && BASH_VERSINFO[1] >= 4) )); then
+        [[ $PS0 == *"$__tart_ps0"* ]] || PS0+=$__tart_ps0
+    else
+        __tart_at_prompt=1
+    fi
+}
+
+if [[ $(declare -p PROMPT_COMMAND 2>/dev/null) == "declare -a"* ]]; then
+    PROMPT_COMMAND=(__tart_prompt_start "${PROMPT_COMMAND[@]}" __tart_prompt_end)
+else
+    PROMPT_COMMAND=__tart_prompt_start$'\n'${PROMPT_COMMAND:+$PROMPT_COMMAND$'\n'}__tart_prompt_end
+fi
+if (( BASH_VERSINFO[0] < 4 || (BASH_VERSINFO[0] == 4 && BASH_VERSINFO[1] < 4) )); then
+    trap '__tart_preexec' DEBUG
+fi
@@ -0,0 +1,44 @@
+# tart shell integration for fish
+#
+# Marks prompts, commands and their exit status with OSC 133 and reports the working
+# directory with OSC 7. Source it from ~/.config/fish/config.fish, or let tart load it for
+# you by turning on `shell_integration`.
+
+status is-interactive; or exit 0
+set -q __tart_integrated; and exit 0
+set -g __tart_integrated 1
+
+# tart found this file by prepending to XDG_DATA_DIRS, which programs run from here
+# shouldn't inherit
+if set -q TART_XDG_DATA_DIRS
+    if test -n "$TART_XDG_DATA_DIRS"
+        set -gx XDG_DATA_DIRS "$TART_XDG_DATA_DIRS"
+    else
+        set -e XDG_DATA_DIRS
+    end
+    set -e TART_XDG_DATA_DIRS
+end
+
+function __tart_prompt_start --on-event fish_prompt
+    printf '\e]7;file://%s%s\a' "$hostname" "$PWD"
+    printf '\e]133;A\a'
+end
+
+function __tart_preexec --on-event fish_preexec
+    printf '\e]133;C\a'
+end
+
+function __tart_postexec --on-event fish_postexec
+    printf '\e]133;D;%s\a' $status
+end
+
+# fish_prompt is only defined once config.fish has run, so wrap it on the first prompt
+function __tart_wrap_prompt --on-event fish_prompt
+    functions -e __tart_wrap_prompt
+    functions -q fish_prompt; or return
+    functions -c fish_prompt __tart_original_prompt
+    function fish_prompt
+        __tart_original_prompt
+        printf '\e]133;B\a'
+    end
+end
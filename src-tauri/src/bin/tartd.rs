@@ -0,0 +1,3 @@
+fn main() {
+    tart_lib::run_daemon()
+}
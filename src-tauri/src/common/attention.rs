@@ -0,0 +1,165 @@
+pub mod notifications {
+    use serde::{Deserialize, Serialize};
+    use tauri::{AppHandle, Emitter, Manager};
+    use tauri_plugin_notification::NotificationExt;
+
+    use crate::common::{app_state::FrontendEvent, shell::shell_name, term::TerminalInfo};
+
+    /// When a terminal should ask for attention, set globally and per terminal.
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+    #[serde(default)]
+    pub struct NotificationPolicy {
+        /// Seconds a command has to run for before its completion is worth a notification
+        pub threshold: u64,
+        pub only_on_failure: bool,
+
+        /// Never notify or badge the tab, whatever the terminal does
+        pub muted: bool,
+    }
+
+    impl Default for NotificationPolicy {
+        fn default() -> Self {
+            NotificationPolicy {
+                threshold: 10,
+                only_on_failure: false,
+                muted: false,
+            }
+        }
+    }
+
+    impl NotificationPolicy {
+        pub fn should_notify(&self, duration: u64, exit_code: Option<i32>) -> bool {
+            let failed = exit_code.is_some_and(|code| code != 0);
+            duration >= self.threshold.saturating_mul(1000) && (failed || !self.only_on_failure)
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    #[serde(tag = "type")]
+    pub enum AttentionReason {
+        /// `duration` is in milliseconds
+        CommandFinished {
+            command: String,
+            exit_code: Option<i32>,
+            duration: u64,
+        },
+        Notification {
+            title: Option<String>,
+            body: String,
+        },
+        Bell,
+    }
+
+    impl AttentionReason {
+        /// The title and body of the desktop notification, bells only badge the tab.
+        fn notification(&self, terminal: String) -> Option<(String, String)> {
+            match self {
+                AttentionReason::CommandFinished {
+                    command,
+                    exit_code,
+                    duration,
+                } => {
+                    let took = format_duration(*duration);
+                    let body = match exit_code {
+                        Some(code) if *code != 0 => {
+                            format!("`{}` failed with exit code {} after {}", command, code, took)
+                        }
+                        _ => format!("`{}` finished after {}", command, took),
+                    };
+                    Some((terminal, body))
+                }
+                AttentionReason::Notification { title, body } => {
+                    Some((title.clone().unwrap_or(terminal), body.clone()))
+                }
+                AttentionReason::Bell => None,
+            }
+        }
+    }
+
+    /// Whether the user is looking at this terminal right now.
+    pub fn is_focused(app: &AppHandle, info: &TerminalInfo) -> bool {
+        info.focused
+            && app
+                .webview_windows()
+                .values()
+                .any(|window| window.is_focused().unwrap_or(false))
+    }
+
+    /// Badges the terminal's tab and shows a desktop notification, unless the terminal is
+    /// muted or already in front of the user.
+    pub fn request_attention(app: &AppHandle, info: &TerminalInfo, reason: AttentionReason) {
+        if info.notifications.muted || is_focused(app, info) {
+            return;
+        }
+        let terminal = info
+            .display_title()
+            .unwrap_or_else(|| shell_name(&info.command));
+        if let Some((title, body)) = reason.notification(terminal) {
+            if let Err(e) = app.notification().builder().title(title).body(body).show() {
+                println!("Failed to show notification: {}", e);
+            }
+        }
+        let _ = app.emit(
+            "tart://event",
+            FrontendEvent::Attention {
+                id: info.id,
+                reason,
+            },
+        );
+    }
+
+    /// A rough human duration such as `42s` or `1h 5m`.
+    fn format_duration(millis: u64) -> String {
+        let seconds = millis / 1000;
+        match seconds {
+            0..=59 => format!("{}s", seconds),
+            60..=3599 => format!("{}m {}s", seconds / 60, seconds % 60),
+            _ => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn policy(threshold: u64, only_on_failure: bool) -> NotificationPolicy {
+            NotificationPolicy {
+                threshold,
+                only_on_failure,
+                muted: false,
+            }
+        }
+
+        #[test]
+        fn notifies_once_over_the_threshold() {
+            let policy = policy(10, false);
+            assert!(!policy.should_notify(9_999, Some(0)));
+            assert!(policy.should_notify(10_000, Some(0)));
+            assert!(policy.should_notify(10_000, None));
+        }
+
+        #[test]
+        fn only_on_failure() {
+            let policy = policy(0, true);
+            assert!(!policy.should_notify(5_000, Some(0)));
+            assert!(!policy.should_notify(5_000, None));
+            assert!(policy.should_notify(5_000, Some(1)));
+            assert!(policy.should_notify(5_000, Some(-1)));
+        }
+
+        #[test]
+        fn huge_threshold_does_not_overflow() {
+            let policy = policy(u64::MAX, false);
+            assert!(!policy.should_notify(u64::MAX - 1, Some(1)));
+            assert!(policy.should_notify(u64::MAX, Some(1)));
+        }
+
+        #[test]
+        fn formats_durations() {
+            assert_eq!(format_duration(999), "0s");
+            assert_eq!(format_duration(42_000), "42s");
+            assert_eq!(format_duration(65_000), "1m 5s");
+            assert_eq!(format_duration(3_900_000), "1h 5m");
+        }
+    }
+}
@@ -0,0 +1,59 @@
+pub mod event_bus {
+    use crossbeam_channel::Sender;
+    use tauri::{AppHandle, Emitter};
+
+    use crate::common::{
+        app_state::{BackendEvent, FrontendEvent},
+        notifications::{self, AttentionReason},
+        term::TerminalInfo,
+        tartd::{Clients, DaemonMessage},
+    };
+
+    /// Where terminals send their events: straight to the GUI's webviews when they live in
+    /// the GUI process, or to every attached GUI when they live in `tartd`.
+    #[derive(Clone)]
+    pub enum EventBus {
+        App(AppHandle),
+
+        #[cfg_attr(not(unix), allow(dead_code))]
+        Daemon {
+            clients: Clients,
+            backend: Sender<BackendEvent>,
+        },
+    }
+
+    impl EventBus {
+        pub fn emit(&self, event: FrontendEvent) {
+            match self {
+                EventBus::App(app) => {
+                    let _ = app.emit("tart://event", event);
+                }
+                EventBus::Daemon { clients, .. } => clients.broadcast(DaemonMessage::Event { event }),
+            }
+        }
+
+        /// Events for the app itself rather than its frontend, e.g. to reap an exited terminal.
+        pub fn emit_backend(&self, event: BackendEvent) {
+            match self {
+                EventBus::App(app) => {
+                    let _ = app.emit("tart://internal", event);
+                }
+                EventBus::Daemon { backend, .. } => {
+                    let _ = backend.send(event);
+                }
+            }
+        }
+
+        pub fn request_attention(&self, info: &TerminalInfo, reason: AttentionReason) {
+            match self {
+                EventBus::App(app) => notifications::request_attention(app, info, reason),
+
+                // Only a GUI knows whether one of its windows has focus, so each decides for itself
+                EventBus::Daemon { clients, .. } => clients.broadcast(DaemonMessage::Attention {
+                    info: Box::new(info.clone()),
+                    reason,
+                }),
+            }
+        }
+    }
+}
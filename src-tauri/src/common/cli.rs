@@ -0,0 +1,499 @@
+pub mod command_line {
+    use std::{
+        collections::HashMap,
+        env, fs,
+        process::{Command, Stdio},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use serde::Serialize;
+    use snafu::{IntoError, OptionExt, ResultExt};
+    use uuid::Uuid;
+
+    use crate::common::{
+        app_state::{AppCommand, CommandResult},
+        error::{InvalidArgumentsSnafu, ReadFileSnafu, SpawnSnafu, TartError},
+        remote_control,
+        single_instance::InstanceLock,
+        term::TerminalOptions,
+    };
+
+    const USAGE: &str = "\
+Usage: tart [OPTIONS] [-- PROGRAM [ARGS...]]
+       tart COMMAND
+
+Opens the tart window. If tart is already running, opens a terminal in it instead.
+
+Options:
+  --cwd DIR         Directory to start in, the current one by default
+  --profile NAME    Start from a saved profile
+  --title TITLE     Tab title
+  --login           Run the shell as a login shell
+  --env KEY=VALUE   Set an environment variable, may be repeated
+  --new-instance    Open a separate instance, even if tart is already running
+
+Commands:
+  new [OPTIONS] [-- PROGRAM [ARGS...]]
+                    Open a terminal, starting tart if it isn't running. Takes the
+                    options above but --new-instance, and --cwd defaults to tart's own
+  list              List the open terminals
+  send ID TEXT      Type TEXT into a terminal, unescaping \\n, \\r, \\t, \\e, \\0, \\\\ and \\x00
+                    to \\x7f
+  kill ID           Close a terminal
+  focus ID          Show a terminal in the tart window
+  command JSON      Run any command the frontend can, e.g. '{\"type\": \"GetTerminals\"}'
+  help              Show this message
+
+Results are printed to stdout as JSON, and errors to stderr.";
+
+    /// How long to wait for an instance that's starting up to start listening.
+    const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// What the command line asks for.
+    pub enum Invocation {
+        /// No command, so open the window
+        Gui(Launch),
+        Help,
+        Command(Box<AppCommand>),
+    }
+
+    /// Opening the window, or handing the arguments to the instance that already has one.
+    #[derive(Default)]
+    pub struct Launch {
+        /// The terminal the arguments ask for, if they ask for one at all
+        pub terminal: Option<Box<AppCommand>>,
+        pub new_instance: bool,
+    }
+
+    impl Launch {
+        /// What to send the running instance: the terminal asked for, or else a shell in the
+        /// directory tart was launched from.
+        fn forwarded(&self) -> AppCommand {
+            match &self.terminal {
+                Some(terminal) => (**terminal).clone(),
+                None => AppCommand::CreateTerminal {
+                    options: TerminalOptions {
+                        cwd: current_dir(),
+                        ..Default::default()
+                    },
+                },
+            }
+        }
+    }
+
+    /// What's left to do once the command line's been dealt with.
+    pub enum Outcome {
+        Exit(i32),
+
+        /// Open the window in this process, holding on to the lock if it's the first instance
+        Open(Launch, Option<InstanceLock>),
+    }
+
+    pub fn parse(args: &[String]) -> Result<Invocation, TartError> {
+        let Some((name, rest)) = args.split_first() else {
+            return Ok(Invocation::Gui(Launch::default()));
+        };
+        let command = match name.as_str() {
+            "help" | "--help" | "-h" => return Ok(Invocation::Help),
+            "new" => parse_new(rest, None)?,
+            "list" => {
+                expect_args(name, rest, 0)?;
+                AppCommand::GetTerminals {}
+            }
+            "send" => {
+                expect_args(name, rest, 2)?;
+                AppCommand::WriteData {
+                    id: parse_id(&rest[0])?,
+                    data: unescape(&rest[1])?,
+                }
+            }
+            "kill" => {
+                expect_args(name, rest, 1)?;
+                AppCommand::RemoveTerminal { id: parse_id(&rest[0])? }
+            }
+            "focus" => {
+                expect_args(name, rest, 1)?;
+                AppCommand::SetFocus { id: Some(parse_id(&rest[0])?) }
+            }
+            "command" => {
+                expect_args(name, rest, 1)?;
+                serde_json::from_str(&rest[0]).map_err(|e| {
+                    InvalidArgumentsSnafu {
+                        reason: format!("Not a valid command: {}", e),
+                    }
+                    .build()
+                })?
+            }
+            _ if name.starts_with('-') => return parse_launch(args).map(Invocation::Gui),
+            _ => {
+                return InvalidArgumentsSnafu {
+                    reason: format!("Unknown command {:?}", name),
+                }
+                .fail()
+            }
+        };
+        Ok(Invocation::Command(Box::new(command)))
+    }
+
+    fn parse_launch(args: &[String]) -> Result<Launch, TartError> {
+        // Only an option when it comes before the program to run
+        let program = args.iter().position(|arg| arg == "--").unwrap_or(args.len());
+        let new_instance = args[..program].iter().any(|arg| arg == "--new-instance");
+        let args: Vec<String> = args
+            .iter()
+            .enumerate()
+            .filter(|(i, arg)| *i >= program || *arg != "--new-instance")
+            .map(|(_, arg)| arg.clone())
+            .collect();
+
+        let terminal = if args.is_empty() {
+            None
+        } else {
+            Some(Box::new(parse_new(&args, current_dir())?))
+        };
+        Ok(Launch { terminal, new_instance })
+    }
+
+    /// The options shared by `tart new` and plain launches, which start in `cwd` unless told otherwise.
+    fn parse_new(args: &[String], cwd: Option<String>) -> Result<AppCommand, TartError> {
+        let mut options = TerminalOptions {
+            cwd,
+            ..Default::default()
+        };
+        let mut profile = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next().cloned().context(InvalidArgumentsSnafu {
+                    reason: format!("{} needs a value", arg),
+                })
+            };
+            match arg.as_str() {
+                "--cwd" => {
+                    // Relative to where the CLI runs, not where the window does
+                    let cwd = value()?;
+                    let absolute = fs::canonicalize(&cwd).context(ReadFileSnafu { path: &cwd })?;
+                    options.cwd = Some(absolute.to_string_lossy().to_string());
+                }
+                "--title" => options.title = Some(value()?),
+                "--profile" => profile = Some(value()?),
+                "--login" => options.login = Some(true),
+                "--env" => {
+                    let variable = value()?;
+                    let (key, value) = variable.split_once('=').context(InvalidArgumentsSnafu {
+                        reason: format!("--env takes KEY=VALUE, got {:?}", variable),
+                    })?;
+                    options
+                        .env
+                        .get_or_insert_with(HashMap::new)
+                        .insert(key.to_string(), value.to_string());
+                }
+                "--" => {
+                    let mut program = args.cloned();
+                    if let Some(command) = program.next() {
+                        options.command = Some(command);
+                        options.args = Some(program.collect());
+                    }
+                    break;
+                }
+                _ => {
+                    return InvalidArgumentsSnafu {
+                        reason: format!("Unknown option {:?}", arg),
+                    }
+                    .fail()
+                }
+            }
+        }
+
+        Ok(match profile {
+            Some(profile) => AppCommand::CreateTerminalFromProfile {
+                profile,
+                overrides: Some(options),
+            },
+            None => AppCommand::CreateTerminal { options },
+        })
+    }
+
+    fn expect_args(name: &str, args: &[String], count: usize) -> Result<(), TartError> {
+        snafu::ensure!(
+            args.len() == count,
+            InvalidArgumentsSnafu {
+                reason: format!("{} takes {} argument(s), got {}", name, count, args.len()),
+            }
+        );
+        Ok(())
+    }
+
+    fn parse_id(id: &str) -> Result<Uuid, TartError> {
+        Uuid::parse_str(id).map_err(|_| {
+            InvalidArgumentsSnafu {
+                reason: format!("{:?} is not a terminal ID", id),
+            }
+            .build()
+        })
+    }
+
+    /// Unescapes `\n`, `\r`, `\t`, `\e`, `\0`, `\\` and `\xHH`, so keys a shell can't easily
+    /// quote can still be sent. `\xHH` only goes up to `\x7f`: anything higher would be part of
+    /// a UTF-8 sequence, which is better typed as the character itself.
+    fn unescape(text: &str) -> Result<String, TartError> {
+        let invalid = |escape: &str| {
+            InvalidArgumentsSnafu {
+                reason: format!("Unknown escape sequence \\{}", escape),
+            }
+            .build()
+        };
+        let mut unescaped = String::with_capacity(text.len());
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                unescaped.push(c);
+                continue;
+            }
+            let escaped = match chars.next() {
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some('t') => '\t',
+                Some('e') => '\x1b',
+                Some('0') => '\0',
+                Some('\\') => '\\',
+                Some('x') => {
+                    let hex: String = chars.by_ref().take(2).collect();
+                    if hex.len() != 2 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                        return Err(invalid(&format!("x{}", hex)));
+                    }
+                    match u8::from_str_radix(&hex, 16) {
+                        Ok(byte) if byte.is_ascii() => char::from(byte),
+                        _ => {
+                            return InvalidArgumentsSnafu {
+                                reason: format!("\\x{} is past \\x7f, type the character itself instead", hex),
+                            }
+                            .fail()
+                        }
+                    }
+                }
+                Some(other) => return Err(invalid(&other.to_string())),
+                None => return Err(invalid("")),
+            };
+            unescaped.push(escaped);
+        }
+        Ok(unescaped)
+    }
+
+    fn current_dir() -> Option<String> {
+        env::current_dir().ok().map(|dir| dir.to_string_lossy().to_string())
+    }
+
+    /// Runs the command line against the running instance, or says to open the window.
+    pub fn run(args: &[String]) -> Outcome {
+        let command = match parse(args) {
+            Ok(Invocation::Gui(launch)) => return open_or_forward(launch),
+            Ok(Invocation::Help) => {
+                println!("{}", USAGE);
+                return Outcome::Exit(0);
+            }
+            Ok(Invocation::Command(command)) => *command,
+            Err(e) => {
+                eprintln!("{}\n\n{}", e, USAGE);
+                return Outcome::Exit(2);
+            }
+        };
+
+        let opens_terminal = matches!(
+            command,
+            AppCommand::CreateTerminal { .. } | AppCommand::CreateTerminalFromProfile { .. }
+        );
+        let sent = match remote_control::send(&command) {
+            Err(TartError::NotRunning) if opens_terminal => start_and_send(&command),
+            sent => sent,
+        };
+        Outcome::Exit(report(sent))
+    }
+
+    /// Opens the window unless another instance already has one, in which case the launch
+    /// becomes a new terminal there instead.
+    fn open_or_forward(launch: Launch) -> Outcome {
+        // Without a socket to forward over, every launch has to be its own instance
+        if launch.new_instance || !cfg!(unix) {
+            return Outcome::Open(launch, None);
+        }
+        match InstanceLock::acquire() {
+            Ok(Some(lock)) => Outcome::Open(launch, Some(lock)),
+            Ok(None) => Outcome::Exit(report(send_when_ready(&launch.forwarded()))),
+            Err(e) => {
+                println!("{}, opening a separate instance", e);
+                Outcome::Open(launch, None)
+            }
+        }
+    }
+
+    /// Prints the result as JSON and returns the exit code to go with it.
+    fn report(sent: Result<CommandResult, TartError>) -> i32 {
+        match sent.as_ref().map(CommandResult::result) {
+            Ok(Ok(value)) => {
+                println!("{}", to_json(value));
+                0
+            }
+            Ok(Err(error)) => {
+                eprintln!("{}", to_json(error));
+                1
+            }
+            Err(e) => {
+                eprintln!("{}", to_json(e));
+                1
+            }
+        }
+    }
+
+    /// Opens the window in a process of its own, then sends `command` once it's listening.
+    fn start_and_send(command: &AppCommand) -> Result<CommandResult, TartError> {
+        // Holding the lock means nothing is running yet, rather than still starting up. It has
+        // to be let go before the new process can take it.
+        if InstanceLock::acquire()?.is_some() {
+            let exe = env::current_exe().context(SpawnSnafu { command: "tart" })?;
+            Command::new(&exe)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .map_err(|e| SpawnSnafu { command: exe.display().to_string() }.into_error(e))?;
+        }
+        send_when_ready(command)
+    }
+
+    /// Sends `command` to an instance that may still be starting up.
+    fn send_when_ready(command: &AppCommand) -> Result<CommandResult, TartError> {
+        let started = Instant::now();
+        loop {
+            match remote_control::send(command) {
+                Err(TartError::NotRunning) if started.elapsed() < STARTUP_TIMEOUT => {
+                    thread::sleep(Duration::from_millis(100))
+                }
+                sent => return sent,
+            }
+        }
+    }
+
+    fn to_json(value: &impl Serialize) -> String {
+        serde_json::to_string_pretty(value).unwrap_or_default()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn args(args: &[&str]) -> Vec<String> {
+            args.iter().map(|arg| arg.to_string()).collect()
+        }
+
+        fn command(list: &[&str]) -> AppCommand {
+            match parse(&args(list)) {
+                Ok(Invocation::Command(command)) => *command,
+                Ok(_) => panic!("{:?} isn't a command", list),
+                Err(e) => panic!("{:?} failed to parse: {}", list, e),
+            }
+        }
+
+        fn invalid(list: &[&str]) -> bool {
+            matches!(parse(&args(list)), Err(TartError::InvalidArguments { .. }))
+        }
+
+        fn launch(list: &[&str]) -> Launch {
+            match parse(&args(list)) {
+                Ok(Invocation::Gui(launch)) => launch,
+                Ok(_) => panic!("{:?} isn't a launch", list),
+                Err(e) => panic!("{:?} failed to parse: {}", list, e),
+            }
+        }
+
+        fn created(command: AppCommand) -> TerminalOptions {
+            match command {
+                AppCommand::CreateTerminal { options } => options,
+                _ => panic!("expected a CreateTerminal"),
+            }
+        }
+
+        #[test]
+        fn parses_commands() {
+            let id = Uuid::new_v4();
+            assert!(matches!(parse(&[]), Ok(Invocation::Gui(Launch { terminal: None, new_instance: false }))));
+            assert!(matches!(parse(&args(&["help"])), Ok(Invocation::Help)));
+            assert!(matches!(parse(&args(&["-h"])), Ok(Invocation::Help)));
+            assert!(matches!(command(&["list"]), AppCommand::GetTerminals {}));
+            assert!(matches!(
+                command(&["send", &id.to_string(), "ls\\n"]),
+                AppCommand::WriteData { id: sent, data } if sent == id && data == "ls\n"
+            ));
+            assert!(matches!(command(&["kill", &id.to_string()]), AppCommand::RemoveTerminal { id: killed } if killed == id));
+            assert!(matches!(command(&["focus", &id.to_string()]), AppCommand::SetFocus { id: Some(focused) } if focused == id));
+            assert!(matches!(command(&["command", r#"{"type": "GetConfig"}"#]), AppCommand::GetConfig {}));
+        }
+
+        #[test]
+        fn rejects_bad_commands() {
+            assert!(invalid(&["bogus"]));
+            assert!(invalid(&["list", "extra"]));
+            assert!(invalid(&["send", "not-an-id", "text"]));
+            assert!(invalid(&["kill"]));
+            assert!(invalid(&["command", "{}"]));
+            assert!(invalid(&["new", "--title"]));
+            assert!(invalid(&["new", "--env", "NO_EQUALS"]));
+            assert!(invalid(&["new", "--bogus"]));
+        }
+
+        #[test]
+        fn parses_new() {
+            let options = created(command(&[
+                "new", "--title", "logs", "--login", "--env", "A=1=2", "--", "tail", "-f", "--title",
+            ]));
+            assert_eq!(options.title.as_deref(), Some("logs"));
+            assert_eq!(options.login, Some(true));
+            assert_eq!(options.env.unwrap()["A"], "1=2");
+            assert_eq!(options.command.as_deref(), Some("tail"));
+            assert_eq!(options.args, Some(args(&["-f", "--title"])));
+
+            // `tart new` starts wherever tart itself runs unless told otherwise
+            assert_eq!(options.cwd, None);
+            let dir = env::temp_dir();
+            let options = created(command(&["new", "--cwd", &dir.to_string_lossy()]));
+            let expected = fs::canonicalize(&dir).unwrap();
+            assert_eq!(options.cwd, Some(expected.to_string_lossy().to_string()));
+
+            assert!(matches!(
+                command(&["new", "--profile", "dev", "--title", "x"]),
+                AppCommand::CreateTerminalFromProfile { profile, overrides: Some(overrides) }
+                    if profile == "dev" && overrides.title.as_deref() == Some("x")
+            ));
+        }
+
+        #[test]
+        fn parses_launches() {
+            let launched = launch(&["--new-instance"]);
+            assert!(launched.new_instance);
+            assert!(launched.terminal.is_none());
+
+            // A plain launch opens its terminal where it was started
+            let launched = launch(&["--title", "x", "--new-instance"]);
+            assert!(launched.new_instance);
+            let options = created(*launched.terminal.unwrap());
+            assert_eq!(options.title.as_deref(), Some("x"));
+            assert_eq!(options.cwd, current_dir());
+
+            // After `--` it's the program's argument, not tart's
+            let launched = launch(&["--", "echo", "--new-instance"]);
+            assert!(!launched.new_instance);
+            assert_eq!(created(*launched.terminal.unwrap()).args, Some(args(&["--new-instance"])));
+        }
+
+        #[test]
+        fn unescapes() {
+            assert_eq!(unescape("a\\nb\\r\\t\\e\\0\\\\").unwrap(), "a\nb\r\t\x1b\0\\");
+            assert_eq!(unescape("\\x41\\x7f\\x7F").unwrap(), "A\x7f\x7f");
+            assert_eq!(unescape("中文 ✓").unwrap(), "中文 ✓");
+            for bad in ["\\x80", "\\xff", "\\x4", "\\x+f", "\\xzz", "\\q", "trailing\\"] {
+                assert!(unescape(bad).is_err(), "{:?} should be rejected", bad);
+            }
+        }
+    }
+}
@@ -0,0 +1,663 @@
+pub mod tartd {
+    // Only unix has a socket to serve on, elsewhere the GUI always runs its terminals in-process
+    #![cfg_attr(not(unix), allow(dead_code))]
+
+    use std::{
+        collections::HashMap,
+        fs,
+        io::{self, BufRead, BufReader, Read, Write},
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex, MutexGuard},
+        thread,
+        time::Duration,
+    };
+
+    #[cfg(unix)]
+    use std::os::unix::{
+        fs::PermissionsExt,
+        net::{UnixListener, UnixStream},
+    };
+
+    use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
+    use serde::{Deserialize, Serialize};
+    use serde_json::Value;
+    use snafu::{IntoError, OptionExt, ResultExt};
+    #[cfg(unix)]
+    use tauri::Emitter;
+    use tauri::{
+        ipc::{Channel, InvokeBody},
+        AppHandle,
+    };
+    use uuid::Uuid;
+
+    #[cfg(unix)]
+    use snafu::ensure;
+
+    #[cfg(unix)]
+    use crate::common::{error::AlreadyRunningSnafu, notifications};
+    use crate::common::{
+        app_state::{AppCommand, AppState, CommandResult, FrontendEvent},
+        error::{DaemonDisconnectedSnafu, ErrorPayload, SocketSnafu, TartError},
+        event_bus::EventBus,
+        notifications::AttentionReason,
+        term::{Subscription, TerminalInfo},
+    };
+
+    /// Where tart keeps its sockets: `$XDG_RUNTIME_DIR/tart`, or a per-user directory in the
+    /// temp directory on systems without one.
+    pub fn socket_dir() -> PathBuf {
+        dirs::runtime_dir().map(|dir| dir.join("tart")).unwrap_or_else(|| {
+            let user = std::env::var("USER").unwrap_or_default();
+            std::env::temp_dir().join(format!("tart-{}", user))
+        })
+    }
+
+    pub fn socket_path() -> PathBuf {
+        socket_dir().join("tartd.sock")
+    }
+
+    /// What a GUI sends the daemon, or the CLI sends a GUI, one JSON object per line. `request`
+    /// ties the reply (and, for subscriptions, the streamed events) back to the request.
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    #[serde(tag = "type")]
+    pub enum DaemonRequest {
+        Command { request: Uuid, command: Box<AppCommand> },
+        Subscribe { request: Uuid, id: Uuid },
+    }
+
+    /// What the daemon sends its GUIs, or a GUI sends the CLI, one JSON object per line.
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    #[serde(tag = "type")]
+    pub enum DaemonMessage {
+        CommandResult {
+            request: Uuid,
+            result: CommandResult,
+        },
+        Subscribed {
+            request: Uuid,
+            result: Result<Subscription, ErrorPayload>,
+        },
+
+        /// An event for the subscription made by `request`
+        Stream { request: Uuid, event: Value },
+
+        /// An event for every window, what the GUI emits as `tart://event` in-process
+        Event { event: FrontendEvent },
+        Attention {
+            info: Box<TerminalInfo>,
+            reason: AttentionReason,
+        },
+    }
+
+    /// The clients connected to a socket, each with the queue its connection writes out.
+    #[derive(Clone, Default)]
+    pub struct Clients(Arc<Mutex<HashMap<Uuid, Sender<DaemonMessage>>>>);
+
+    impl Clients {
+        /// Registers a client, returning its ID and both ends of its queue.
+        pub fn add(&self) -> (Uuid, Sender<DaemonMessage>, Receiver<DaemonMessage>) {
+            let id = Uuid::new_v4();
+            let (sender, receiver) = unbounded();
+            if let Ok(mut clients) = self.0.lock() {
+                clients.insert(id, sender.clone());
+            }
+            (id, sender, receiver)
+        }
+
+        fn remove(&self, id: Uuid) {
+            if let Ok(mut clients) = self.0.lock() {
+                clients.remove(&id);
+            }
+        }
+
+        pub fn broadcast(&self, message: DaemonMessage) {
+            if let Ok(mut clients) = self.0.lock() {
+                clients.retain(|_, client| client.send(message.clone()).is_ok());
+            }
+        }
+
+        #[cfg(test)]
+        pub fn count(&self) -> usize {
+            self.0.lock().map(|clients| clients.len()).unwrap_or(0)
+        }
+    }
+
+    impl std::fmt::Debug for Clients {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let count = self.0.lock().map(|clients| clients.len()).unwrap_or(0);
+            f.debug_tuple("Clients").field(&count).finish()
+        }
+    }
+
+    /// Runs the daemon, which owns every terminal its GUIs create, until it's killed.
+    #[cfg(unix)]
+    pub fn serve(path: &Path) -> Result<(), TartError> {
+        let listener = bind(path)?;
+        let state = AppState::new();
+        let clients = Clients::default();
+        let (backend, events) = unbounded();
+        let bus = EventBus::Daemon {
+            clients: clients.clone(),
+            backend,
+        };
+        state.watch_config(&bus);
+
+        let background = (state.clone(), bus.clone());
+        thread::spawn(move || {
+            let (state, bus) = background;
+            for event in events {
+                state.handle_backend_event(&bus, event);
+            }
+        });
+
+        println!("Listening on {}", path.display());
+        for stream in listener.incoming() {
+            let (reader, writer) = match stream.and_then(|stream| Ok((stream.try_clone()?, stream))) {
+                Ok(halves) => halves,
+                Err(e) => {
+                    println!("Failed to accept a connection: {}", e);
+                    continue;
+                }
+            };
+            let (state, bus, clients) = (state.clone(), bus.clone(), clients.clone());
+            thread::spawn(move || {
+                serve_connection(&clients, reader, writer, |outgoing, request| {
+                    handle_request(&state, &bus, outgoing, request)
+                })
+            });
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn serve(path: &Path) -> Result<(), TartError> {
+        Err(SocketSnafu { path }.into_error(io::ErrorKind::Unsupported.into()))
+    }
+
+    /// Listens on `path`, taking over the socket if whoever left it there is gone.
+    #[cfg(unix)]
+    pub fn bind(path: &Path) -> Result<UnixListener, TartError> {
+        create_socket_dir(path)?;
+        if path.exists() {
+            // Nobody answering means the last owner didn't get to clean up after itself
+            ensure!(UnixStream::connect(path).is_err(), AlreadyRunningSnafu { path });
+            fs::remove_file(path).context(SocketSnafu { path })?;
+        }
+        UnixListener::bind(path).context(SocketSnafu { path })
+    }
+
+    /// Creates the directory `path` goes in, which only we may use.
+    pub fn create_socket_dir(path: &Path) -> Result<(), TartError> {
+        let Some(dir) = path.parent() else {
+            return Ok(());
+        };
+        fs::create_dir_all(dir).context(SocketSnafu { path })?;
+
+        // Anyone who can connect can run commands as us
+        #[cfg(unix)]
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700)).context(SocketSnafu { path })?;
+        Ok(())
+    }
+
+    /// Connects to the socket at `path`, or `None` if nothing is listening there.
+    #[cfg(unix)]
+    pub fn connect(path: &Path) -> Result<Option<UnixStream>, TartError> {
+        match UnixStream::connect(path) {
+            Ok(stream) => Ok(Some(stream)),
+            Err(e) if matches!(e.kind(), io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused) => Ok(None),
+            Err(e) => Err(SocketSnafu { path }.into_error(e)),
+        }
+    }
+
+    /// Answers a client's requests with `handle` until it disconnects.
+    pub fn serve_connection(
+        clients: &Clients,
+        reader: impl Read,
+        mut writer: impl Write + Send + 'static,
+        handle: impl Fn(&Sender<DaemonMessage>, DaemonRequest) -> DaemonMessage,
+    ) {
+        let (client, outgoing, messages) = clients.add();
+
+        // Exits on the first failed write after the client goes away, which also ends its subscriptions
+        thread::spawn(move || {
+            for message in messages {
+                if write_message(&mut writer, &message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        for line in BufReader::new(reader).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            match serde_json::from_str::<DaemonRequest>(&line) {
+                Ok(request) => {
+                    let _ = outgoing.send(handle(&outgoing, request));
+                }
+                Err(e) => println!("Ignoring a malformed request: {}", e),
+            }
+        }
+        clients.remove(client);
+    }
+
+    fn handle_request(
+        state: &AppState,
+        bus: &EventBus,
+        outgoing: &Sender<DaemonMessage>,
+        request: DaemonRequest,
+    ) -> DaemonMessage {
+        match request {
+            DaemonRequest::Command { request, command } => DaemonMessage::CommandResult {
+                request,
+                result: state.run_command(bus, *command),
+            },
+            DaemonRequest::Subscribe { request, id } => DaemonMessage::Subscribed {
+                request,
+                result: state.subscribe(id, stream(outgoing, request)).map_err(|e| e.payload()),
+            },
+        }
+    }
+
+    /// A channel that forwards a subscription's events to the client that made `request`.
+    pub fn stream(outgoing: &Sender<DaemonMessage>, request: Uuid) -> Channel<FrontendEvent> {
+        let outgoing = outgoing.clone();
+        Channel::new(move |body| {
+            if let InvokeBody::Json(event) = body {
+                outgoing
+                    .send(DaemonMessage::Stream { request, event })
+                    .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+            }
+            Ok(())
+        })
+    }
+
+    pub fn write_message(writer: &mut impl Write, message: &impl Serialize) -> io::Result<()> {
+        let mut line = serde_json::to_vec(message)?;
+        line.push(b'\n');
+        writer.write_all(&line)?;
+        writer.flush()
+    }
+
+    /// A subscription made through the daemon, forwarded to the webview that asked for it.
+    struct Stream {
+        id: Uuid,
+        subscription: Option<Uuid>,
+        channel: Channel<FrontendEvent>,
+    }
+
+    /// How long a GUI waits on the daemon's reply before treating it as gone.
+    const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// A GUI's connection to `tartd`.
+    #[derive(Clone)]
+    pub struct DaemonClient {
+        writer: Arc<Mutex<Box<dyn Write + Send>>>,
+        timeout: Duration,
+        pending: Arc<Mutex<HashMap<Uuid, Sender<DaemonMessage>>>>,
+        streams: Arc<Mutex<HashMap<Uuid, Stream>>>,
+    }
+
+    impl DaemonClient {
+        /// Attaches to the daemon listening on `path`, or `None` if there isn't one.
+        #[cfg(unix)]
+        pub fn connect(app: &AppHandle, path: &Path) -> Result<Option<Self>, TartError> {
+            let Some(stream) = connect(path)? else {
+                return Ok(None);
+            };
+            let reader = stream.try_clone().context(SocketSnafu { path })?;
+            let client = DaemonClient::new(Box::new(stream), REQUEST_TIMEOUT);
+            let app = app.clone();
+            client.listen(reader, move |message| match message {
+                DaemonMessage::Event { event } => {
+                    let _ = app.emit("tart://event", event);
+                }
+                DaemonMessage::Attention { info, reason } => {
+                    notifications::request_attention(&app, &info, reason)
+                }
+                _ => {}
+            });
+            Ok(Some(client))
+        }
+
+        #[cfg(not(unix))]
+        pub fn connect(_app: &AppHandle, _path: &Path) -> Result<Option<Self>, TartError> {
+            Ok(None)
+        }
+
+        fn new(writer: Box<dyn Write + Send>, timeout: Duration) -> Self {
+            DaemonClient {
+                writer: Arc::new(Mutex::new(writer)),
+                timeout,
+                pending: Arc::new(Mutex::new(HashMap::new())),
+                streams: Arc::new(Mutex::new(HashMap::new())),
+            }
+        }
+
+        /// Reads the daemon's messages, passing the ones meant for every window to `broadcast`.
+        fn listen(
+            &self,
+            reader: impl Read + Send + 'static,
+            broadcast: impl Fn(DaemonMessage) + Send + 'static,
+        ) {
+            let client = self.clone();
+            thread::spawn(move || {
+                for line in BufReader::new(reader).lines() {
+                    let Ok(line) = line else {
+                        break;
+                    };
+                    match serde_json::from_str::<DaemonMessage>(&line) {
+                        Ok(message) => {
+                            if let Some(message) = client.dispatch(message) {
+                                broadcast(message);
+                            }
+                        }
+                        Err(e) => println!("Ignoring a malformed message from tartd: {}", e),
+                    }
+                }
+                println!("Lost the connection to tartd");
+
+                // Dropping the reply senders fails everyone still waiting instead of leaving them hanging
+                client.lock_pending().clear();
+                client.lock_streams().clear();
+            });
+        }
+
+        /// Hands replies and streamed events to whoever is waiting on them, and gives back
+        /// the messages meant for every window.
+        fn dispatch(&self, message: DaemonMessage) -> Option<DaemonMessage> {
+            match message {
+                DaemonMessage::CommandResult { request, .. } | DaemonMessage::Subscribed { request, .. } => {
+                    let waiting = self.lock_pending().remove(&request);
+                    if let Some(reply) = waiting {
+                        let _ = reply.send(message);
+                    }
+                    None
+                }
+                DaemonMessage::Stream { request, event } => {
+                    let Ok(event) = serde_json::from_value::<FrontendEvent>(event) else {
+                        return None;
+                    };
+                    let mut streams = self.lock_streams();
+                    let stream = streams.get(&request)?;
+                    if stream.channel.send(event).is_err() {
+                        // The webview went away without unsubscribing, so do it for it
+                        let stream = streams.remove(&request);
+                        drop(streams);
+                        if let Some(Stream { id, subscription: Some(subscription), .. }) = stream {
+                            let _ = self.send(&DaemonRequest::Command {
+                                request: Uuid::new_v4(),
+                                command: Box::new(AppCommand::Unsubscribe { id, subscription }),
+                            });
+                        }
+                    }
+                    None
+                }
+                DaemonMessage::Event { .. } | DaemonMessage::Attention { .. } => Some(message),
+            }
+        }
+
+        pub fn run_command(&self, command: AppCommand) -> Result<CommandResult, TartError> {
+            if let AppCommand::Unsubscribe { subscription, .. } = command {
+                self.lock_streams()
+                    .retain(|_, stream| stream.subscription != Some(subscription));
+            }
+            let request = Uuid::new_v4();
+            let message = DaemonRequest::Command {
+                request,
+                command: Box::new(command),
+            };
+            match self.request(request, message)? {
+                DaemonMessage::CommandResult { result, .. } => Ok(result),
+                _ => DaemonDisconnectedSnafu.fail(),
+            }
+        }
+
+        pub fn subscribe(
+            &self,
+            id: Uuid,
+            channel: Channel<FrontendEvent>,
+        ) -> Result<Subscription, ErrorPayload> {
+            let request = Uuid::new_v4();
+
+            // Output can arrive ahead of the reply, so the stream has to be in place first
+            self.lock_streams().insert(
+                request,
+                Stream {
+                    id,
+                    subscription: None,
+                    channel,
+                },
+            );
+            let result = match self.request(request, DaemonRequest::Subscribe { request, id }) {
+                Ok(DaemonMessage::Subscribed { result, .. }) => result,
+                Ok(_) => Err(DaemonDisconnectedSnafu.build().payload()),
+                Err(e) => Err(e.payload()),
+            };
+
+            let mut streams = self.lock_streams();
+            match &result {
+                Ok(subscribed) => {
+                    if let Some(stream) = streams.get_mut(&request) {
+                        stream.subscription = Some(subscribed.subscription);
+                    }
+                }
+                Err(_) => {
+                    streams.remove(&request);
+                }
+            }
+            result
+        }
+
+        /// Sends a request and waits for the daemon's reply to it. A daemon that doesn't answer
+        /// in time is as good as gone, rather than a reason to hang the window.
+        fn request(&self, request: Uuid, message: DaemonRequest) -> Result<DaemonMessage, TartError> {
+            let (reply, replied) = bounded(1);
+            self.lock_pending().insert(request, reply);
+            if let Err(e) = self.send(&message) {
+                self.lock_pending().remove(&request);
+                return Err(e);
+            }
+            let reply = replied.recv_timeout(self.timeout);
+            self.lock_pending().remove(&request);
+            reply.ok().context(DaemonDisconnectedSnafu)
+        }
+
+        fn send(&self, message: &DaemonRequest) -> Result<(), TartError> {
+            let mut writer = self.writer.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            write_message(&mut *writer, message).map_err(|_| DaemonDisconnectedSnafu.build())
+        }
+
+        fn lock_pending(&self) -> MutexGuard<'_, HashMap<Uuid, Sender<DaemonMessage>>> {
+            self.pending.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+        }
+
+        fn lock_streams(&self) -> MutexGuard<'_, HashMap<Uuid, Stream>> {
+            self.streams.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+        }
+    }
+
+    /// Where the GUI's terminals live.
+    pub enum Backend {
+        /// In the GUI process, so they close along with it
+        Local(AppState),
+
+        /// In `tartd`, so they outlive the GUI and are still there when it reattaches
+        Daemon(DaemonClient),
+    }
+
+    impl Backend {
+        /// Attaches to `tartd` if it's running, and otherwise hosts the terminals itself.
+        pub fn start(app: &AppHandle) -> Backend {
+            let path = socket_path();
+            match DaemonClient::connect(app, &path) {
+                Ok(Some(client)) => {
+                    println!("Attached to tartd on {}", path.display());
+                    return Backend::Daemon(client);
+                }
+                Ok(None) => (),
+                Err(e) => println!("{}, running terminals in-process instead", e),
+            }
+
+            let state = AppState::new();
+            state.run_in_background(app);
+            state.watch_config(&EventBus::App(app.clone()));
+            Backend::Local(state)
+        }
+
+        pub fn run_command(&self, app: &AppHandle, command: AppCommand) -> Result<CommandResult, TartError> {
+            match self {
+                Backend::Local(state) => Ok(state.run_command(&EventBus::App(app.clone()), command)),
+                Backend::Daemon(client) => client.run_command(command),
+            }
+        }
+
+        pub fn subscribe(&self, id: Uuid, channel: Channel<FrontendEvent>) -> Result<Subscription, ErrorPayload> {
+            match self {
+                Backend::Local(state) => state.subscribe(id, channel).map_err(|e| e.payload()),
+                Backend::Daemon(client) => client.subscribe(id, channel),
+            }
+        }
+
+        /// Saves the terminals that are about to close along with the GUI. The daemon's
+        /// terminals stay open, so there's nothing to save for those.
+        pub fn save_session(&self) {
+            if let Backend::Local(state) = self {
+                if let Err(e) = state.save_session() {
+                    println!("Failed to save the session: {}", e);
+                }
+            }
+        }
+    }
+
+    #[cfg(all(test, unix))]
+    mod tests {
+        use serde_json::json;
+
+        use super::*;
+        use crate::common::{config::ScrollbackConfig, screen::Screen};
+
+        /// A client talking to whatever the test writes on the other end of the socket
+        fn client(timeout: Duration) -> (DaemonClient, UnixStream, Arc<Mutex<Vec<DaemonMessage>>>) {
+            let (ours, theirs) = UnixStream::pair().unwrap();
+            let client = DaemonClient::new(Box::new(ours.try_clone().unwrap()), timeout);
+            let broadcasts = Arc::new(Mutex::new(Vec::new()));
+            let received = broadcasts.clone();
+            client.listen(ours, move |message| received.lock().unwrap().push(message));
+            (client, theirs, broadcasts)
+        }
+
+        fn read_request(reader: &mut impl BufRead) -> DaemonRequest {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            serde_json::from_str(&line).unwrap()
+        }
+
+        fn command_result(request: Uuid, result: Value) -> DaemonMessage {
+            let result = json!({
+                "id": request,
+                "command": { "type": "GetTerminals" },
+                "result": { "Ok": result },
+            });
+            DaemonMessage::CommandResult {
+                request,
+                result: serde_json::from_value(result).unwrap(),
+            }
+        }
+
+        #[test]
+        fn replies_find_their_request() {
+            let (client, mut daemon, broadcasts) = client(REQUEST_TIMEOUT);
+            let answer = thread::spawn(move || {
+                let DaemonRequest::Command { request, .. } = read_request(&mut BufReader::new(&daemon)) else {
+                    panic!("expected a command");
+                };
+                let event = FrontendEvent::ProfilesChanged {};
+                write_message(&mut daemon, &command_result(Uuid::new_v4(), json!("someone else's"))).unwrap();
+                write_message(&mut daemon, &DaemonMessage::Event { event }).unwrap();
+                write_message(&mut daemon, &command_result(request, json!("ours"))).unwrap();
+                daemon
+            });
+
+            let result = client.run_command(AppCommand::GetTerminals {}).unwrap();
+            assert_eq!(result.result(), &Ok(json!("ours")));
+            let broadcasts = broadcasts.lock().unwrap();
+            assert!(matches!(broadcasts[..], [DaemonMessage::Event { .. }]));
+            answer.join().unwrap();
+        }
+
+        #[test]
+        fn output_ahead_of_the_subscription_reply_is_kept() {
+            let (client, mut daemon, _) = client(REQUEST_TIMEOUT);
+            let subscription = Uuid::new_v4();
+            let answer = thread::spawn(move || {
+                let DaemonRequest::Subscribe { request, id } = read_request(&mut BufReader::new(&daemon)) else {
+                    panic!("expected a subscription");
+                };
+                for _ in 0..2 {
+                    let event = serde_json::to_value(FrontendEvent::TerminalCreated { id }).unwrap();
+                    write_message(&mut daemon, &DaemonMessage::Stream { request, event }).unwrap();
+                }
+                let snapshot = Screen::new(2, 2, ScrollbackConfig::default()).snapshot();
+                let result = Ok(Subscription { subscription, snapshot });
+                write_message(&mut daemon, &DaemonMessage::Subscribed { request, result }).unwrap();
+                daemon
+            });
+
+            let received = Arc::new(Mutex::new(0));
+            let events = received.clone();
+            let channel = Channel::new(move |_| {
+                *events.lock().unwrap() += 1;
+                Ok(())
+            });
+            let subscribed = client.subscribe(Uuid::new_v4(), channel).unwrap();
+            assert_eq!(subscribed.subscription, subscription);
+            assert_eq!(*received.lock().unwrap(), 2);
+            answer.join().unwrap();
+        }
+
+        #[test]
+        fn silent_daemon_times_out() {
+            let (client, _daemon, _) = client(Duration::from_millis(50));
+            let error = client.run_command(AppCommand::GetTerminals {}).unwrap_err();
+            assert_eq!(error.kind(), "daemon_disconnected");
+            assert!(client.lock_pending().is_empty());
+        }
+
+        #[test]
+        fn connection_answers_each_line() {
+            let (ours, theirs) = UnixStream::pair().unwrap();
+            let clients = Clients::default();
+            let serving = clients.clone();
+            let server = thread::spawn(move || {
+                serve_connection(&serving, ours.try_clone().unwrap(), ours, |outgoing, request| {
+                    let DaemonRequest::Command { request, .. } = request else {
+                        panic!("expected a command");
+                    };
+                    // Events a command sets off go out ahead of its reply
+                    let event = json!({ "type": "ProfilesChanged" });
+                    outgoing.send(DaemonMessage::Stream { request, event }).unwrap();
+                    command_result(request, json!(null))
+                })
+            });
+
+            let request = Uuid::new_v4();
+            let command = DaemonRequest::Command {
+                request,
+                command: Box::new(AppCommand::GetTerminals {}),
+            };
+            let mut writer = theirs.try_clone().unwrap();
+            writer.write_all(b"not json\n").unwrap();
+            write_message(&mut writer, &command).unwrap();
+
+            let mut lines = BufReader::new(theirs.try_clone().unwrap()).lines();
+            let mut next = || serde_json::from_str::<DaemonMessage>(&lines.next().unwrap().unwrap()).unwrap();
+            assert!(matches!(next(), DaemonMessage::Stream { request: r, .. } if r == request));
+            assert!(matches!(next(), DaemonMessage::CommandResult { request: r, .. } if r == request));
+
+            theirs.shutdown(std::net::Shutdown::Write).unwrap();
+            server.join().unwrap();
+            assert_eq!(clients.count(), 0);
+        }
+    }
+}
@@ -0,0 +1,1603 @@
+pub mod screen {
+    use std::{collections::VecDeque, fmt, fmt::Write};
+
+    use portable_pty::PtySize;
+    use serde::{Deserialize, Serialize};
+    use unicode_width::UnicodeWidthChar;
+    use vte::{Params, Parser, Perform};
+
+    use crate::common::config::ScrollbackConfig;
+
+    const TAB_WIDTH: usize = 8;
+
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum Color {
+        #[default]
+        Default,
+        Indexed(u8),
+        Rgb(u8, u8, u8),
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct Attributes {
+        pub foreground: Color,
+        pub background: Color,
+        pub bold: bool,
+        pub dim: bool,
+        pub italic: bool,
+        pub underline: bool,
+        pub blink: bool,
+        pub inverse: bool,
+        pub hidden: bool,
+        pub strikethrough: bool,
+    }
+
+    impl Attributes {
+        /// Erased cells keep the current background color (BCE) and nothing else.
+        fn erased(&self) -> Self {
+            Attributes {
+                background: self.background,
+                ..Attributes::default()
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    pub struct Cell {
+        pub c: char,
+
+        /// Zero-width characters (combining marks, variation selectors) attached to `c`
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub combining: Vec<char>,
+
+        /// 2 for the first half of a wide character, 0 for the second half
+        pub width: u8,
+        pub attrs: Attributes,
+    }
+
+    impl Cell {
+        fn blank(attrs: Attributes) -> Self {
+            Cell {
+                c: ' ',
+                combining: Vec::new(),
+                width: 1,
+                attrs,
+            }
+        }
+
+        fn spacer(attrs: Attributes) -> Self {
+            Cell {
+                width: 0,
+                ..Cell::blank(attrs)
+            }
+        }
+    }
+
+    impl Default for Cell {
+        fn default() -> Self {
+            Cell::blank(Attributes::default())
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    pub struct Row {
+        pub cells: Vec<Cell>,
+
+        /// Set when the text on this row continues on the next one because of autowrap
+        pub wrapped: bool,
+    }
+
+    impl Row {
+        fn blank(cols: usize, attrs: Attributes) -> Self {
+            Row {
+                cells: vec![Cell::blank(attrs); cols],
+                wrapped: false,
+            }
+        }
+
+        pub fn text(&self) -> String {
+            self.text_between(0, self.cells.len())
+        }
+
+        /// The text of the cells in `start..end`, with trailing blanks trimmed.
+        fn text_between(&self, start: usize, end: usize) -> String {
+            let mut text = String::new();
+            let end = end.min(self.cells.len());
+            for cell in self.cells[start.min(end)..end].iter().filter(|cell| cell.width > 0) {
+                text.push(cell.c);
+                text.extend(&cell.combining);
+            }
+            text.trim_end().to_string()
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+    pub struct Cursor {
+        pub row: usize,
+        pub col: usize,
+        pub attrs: Attributes,
+
+        /// The last column was just written, the next printable character wraps first
+        pub pending_wrap: bool,
+    }
+
+    /// The character sets that can be designated to G0 and G1. Only DEC special graphics,
+    /// which turns ASCII letters into line drawing, differs from plain ASCII.
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    enum Charset {
+        #[default]
+        Ascii,
+        DecGraphics,
+    }
+
+    impl Charset {
+        fn map(self, c: char) -> char {
+            match (self, c) {
+                (Charset::DecGraphics, '_'..='~') => {
+                    const GRAPHICS: [char; 32] = [
+                        ' ', '◆', '▒', '␉', '␌', '␍', '␊', '°', '±', '␤', '␋', '┘', '┐', '┌', '└', '┼',
+                        '⎺', '⎻', '─', '⎼', '⎽', '├', '┤', '┴', '┬', '│', '≤', '≥', 'π', '≠', '£', '·',
+                    ];
+                    GRAPHICS[c as usize - '_' as usize]
+                }
+                _ => c,
+            }
+        }
+    }
+
+    /// G0 and G1, and which of them SO/SI has shifted in.
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    struct Charsets {
+        designated: [Charset; 2],
+        shifted_out: bool,
+    }
+
+    impl Charsets {
+        fn map(&self, c: char) -> char {
+            self.designated[usize::from(self.shifted_out)].map(c)
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, Default)]
+    struct SavedCursor {
+        cursor: Cursor,
+        origin: bool,
+        charsets: Charsets,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+    pub struct Modes {
+        pub autowrap: bool,
+        pub origin: bool,
+        pub insert: bool,
+        pub cursor_visible: bool,
+        pub application_cursor: bool,
+        pub bracketed_paste: bool,
+        pub alternate_screen: bool,
+    }
+
+    impl Default for Modes {
+        fn default() -> Self {
+            Modes {
+                autowrap: true,
+                origin: false,
+                insert: false,
+                cursor_visible: true,
+                application_cursor: false,
+                bracketed_paste: false,
+                alternate_screen: false,
+            }
+        }
+    }
+
+    /// Rows that scrolled off the top of the primary screen, oldest first.
+    #[derive(Clone, Debug, Default)]
+    struct Scrollback {
+        rows: VecDeque<Row>,
+        bytes: usize,
+        limit: ScrollbackConfig,
+    }
+
+    impl Scrollback {
+        fn new(limit: ScrollbackConfig) -> Self {
+            Scrollback {
+                rows: VecDeque::new(),
+                bytes: 0,
+                limit,
+            }
+        }
+
+        /// What a row counts against the byte limit: its text plus a line break.
+        fn cost(row: &Row) -> usize {
+            row.text().len() + 1
+        }
+
+        fn push(&mut self, row: Row) {
+            if self.limit.lines == 0 {
+                return;
+            }
+            self.bytes += Scrollback::cost(&row);
+            self.rows.push_back(row);
+            self.trim();
+        }
+
+        /// Switches to a new limit, e.g. after a config change, dropping whatever no longer fits.
+        fn set_limit(&mut self, limit: ScrollbackConfig) {
+            self.limit = limit;
+            self.trim();
+        }
+
+        /// Drops the oldest rows until both limits are met again.
+        fn trim(&mut self) {
+            while self.rows.len() > self.limit.lines || self.bytes > self.limit.bytes {
+                match self.rows.pop_front() {
+                    Some(oldest) => self.bytes -= Scrollback::cost(&oldest),
+                    None => break,
+                }
+            }
+        }
+
+        fn clear(&mut self) {
+            self.rows.clear();
+            self.bytes = 0;
+        }
+    }
+
+    /// A point in the shell's prompt/command cycle, reported through OSC 133 (FinalTerm).
+    /// Lines count every row that has ever scrolled off the primary screen, so they stay put
+    /// as output scrolls by.
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    #[serde(tag = "type")]
+    pub enum ShellMark {
+        PromptStart { line: u64 },
+        CommandStart { line: u64 },
+
+        /// The command was submitted, `command` is whatever was typed after the prompt
+        OutputStart { line: u64, command: String },
+        CommandFinished { line: u64, exit_code: Option<i32> },
+    }
+
+    /// Something a program asked to bring to the user's attention.
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    #[serde(tag = "type")]
+    pub enum Alert {
+        Bell,
+
+        /// From OSC 9 (iTerm2) or OSC 777;notify (urxvt), only the latter has a title
+        Notification { title: Option<String>, body: String },
+    }
+
+    /// Everything needed to show a terminal exactly as it is, e.g. in a newly mounted view.
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct Snapshot {
+        pub rows: usize,
+        pub cols: usize,
+        pub scrollback: usize,
+        pub cursor: Cursor,
+        pub modes: Modes,
+
+        /// Escape sequences that recreate the scrollback and screen when written to a blank
+        /// terminal of the same size
+        pub data: String,
+    }
+
+    /// The grid of a terminal as a VT100/xterm-compatible program expects to see it.
+    #[derive(Clone, Debug)]
+    pub struct Screen {
+        rows: usize,
+        cols: usize,
+        grid: Vec<Row>,
+
+        /// The grid that isn't being shown, i.e. the primary one while on the alternate screen
+        inactive: Vec<Row>,
+        cursor: Cursor,
+        saved: SavedCursor,
+        inactive_saved: SavedCursor,
+        charsets: Charsets,
+        modes: Modes,
+        scroll_top: usize,
+        scroll_bottom: usize,
+        tabs: Vec<bool>,
+        last_printed: Option<char>,
+        scrollback: Scrollback,
+
+        /// As last set by the program through OSC 0/2 and OSC 0/1
+        title: Option<String>,
+        icon_name: Option<String>,
+
+        /// As reported by the shell through OSC 7
+        working_directory: Option<String>,
+
+        /// How many rows have scrolled off the top of the primary screen since it was created
+        lines_scrolled: u64,
+
+        /// Where the user started typing, as of the last OSC 133;B
+        command_start: Option<(u64, usize)>,
+        marks: Vec<ShellMark>,
+        alerts: Vec<Alert>,
+    }
+
+    impl Screen {
+        pub fn new(rows: usize, cols: usize, scrollback: ScrollbackConfig) -> Self {
+            let rows = rows.max(1);
+            let cols = cols.max(1);
+            Screen {
+                rows,
+                cols,
+                grid: vec![Row::blank(cols, Attributes::default()); rows],
+                inactive: vec![Row::blank(cols, Attributes::default()); rows],
+                cursor: Cursor::default(),
+                saved: SavedCursor::default(),
+                inactive_saved: SavedCursor::default(),
+                charsets: Charsets::default(),
+                modes: Modes::default(),
+                scroll_top: 0,
+                scroll_bottom: rows - 1,
+                tabs: Screen::default_tabs(cols),
+                last_printed: None,
+                scrollback: Scrollback::new(scrollback),
+                title: None,
+                icon_name: None,
+                working_directory: None,
+                lines_scrolled: 0,
+                command_start: None,
+                marks: Vec::new(),
+                alerts: Vec::new(),
+            }
+        }
+
+        fn default_tabs(cols: usize) -> Vec<bool> {
+            (0..cols).map(|col| col > 0 && col % TAB_WIDTH == 0).collect()
+        }
+
+        pub fn size(&self) -> (usize, usize) {
+            (self.rows, self.cols)
+        }
+
+        pub fn cursor(&self) -> &Cursor {
+            &self.cursor
+        }
+
+        pub fn modes(&self) -> &Modes {
+            &self.modes
+        }
+
+        pub fn title(&self) -> Option<&str> {
+            self.title.as_deref()
+        }
+
+        pub fn icon_name(&self) -> Option<&str> {
+            self.icon_name.as_deref()
+        }
+
+        pub fn working_directory(&self) -> Option<&str> {
+            self.working_directory.as_deref()
+        }
+
+        /// Takes the shell integration marks seen since the last call.
+        pub fn take_marks(&mut self) -> Vec<ShellMark> {
+            std::mem::take(&mut self.marks)
+        }
+
+        /// Takes the bells and notifications seen since the last call.
+        pub fn take_alerts(&mut self) -> Vec<Alert> {
+            std::mem::take(&mut self.alerts)
+        }
+
+        /// The rows currently on screen, whichever of the primary or alternate grid that is.
+        pub fn rows(&self) -> &[Row] {
+            &self.grid
+        }
+
+        /// The visible text with trailing blanks trimmed, one line per row.
+        pub fn text(&self) -> String {
+            self.grid.iter().map(Row::text).collect::<Vec<_>>().join("\n")
+        }
+
+        pub fn resize(&mut self, rows: usize, cols: usize) {
+            let rows = rows.max(1);
+            let cols = cols.max(1);
+            let attrs = Attributes::default();
+
+            // Keep the cursor's row on screen by dropping rows off the top, as xterm does
+            if self.cursor.row >= rows {
+                let excess = self.cursor.row + 1 - rows;
+                let dropped = self.grid.drain(..excess).collect::<Vec<_>>();
+                if !self.modes.alternate_screen {
+                    self.lines_scrolled += dropped.len() as u64;
+                    for row in dropped {
+                        self.scrollback.push(row);
+                    }
+                }
+                self.cursor.row -= excess;
+            }
+            for grid in [&mut self.grid, &mut self.inactive] {
+                grid.resize(rows, Row::blank(cols, attrs));
+                for row in grid.iter_mut() {
+                    row.cells.resize(cols, Cell::blank(attrs));
+                    if let Some(last) = row.cells.last_mut() {
+                        // Don't leave half of a wide character at the new edge
+                        if last.width == 2 {
+                            *last = Cell::blank(last.attrs);
+                        }
+                    }
+                }
+            }
+
+            self.rows = rows;
+            self.cols = cols;
+            self.scroll_top = 0;
+            self.scroll_bottom = rows - 1;
+            self.tabs = Screen::default_tabs(cols);
+            self.cursor.row = self.cursor.row.min(rows - 1);
+            self.cursor.col = self.cursor.col.min(cols - 1);
+            self.cursor.pending_wrap = false;
+        }
+
+        fn blank_row(&self) -> Row {
+            Row::blank(self.cols, self.cursor.attrs.erased())
+        }
+
+        fn blank_cell(&self) -> Cell {
+            Cell::blank(self.cursor.attrs.erased())
+        }
+
+        /// Clears the other half of a wide character that is about to be partially overwritten.
+        fn split_wide(&mut self, row: usize, col: usize) {
+            let cells = &mut self.grid[row].cells;
+            match cells.get(col).map(|cell| cell.width) {
+                Some(2) if col + 1 < cells.len() => {
+                    cells[col + 1] = Cell::blank(cells[col + 1].attrs);
+                }
+                Some(0) if col > 0 => {
+                    cells[col - 1] = Cell::blank(cells[col - 1].attrs);
+                }
+                _ => (),
+            }
+        }
+
+        fn print_char(&mut self, c: char) {
+            let width = c.width().unwrap_or(0);
+            if width == 0 {
+                self.combine(c);
+                return;
+            }
+
+            if self.cursor.pending_wrap && self.modes.autowrap {
+                self.wrap();
+            }
+            if width == 2 && self.cursor.col + 1 >= self.cols {
+                if !self.modes.autowrap || self.cols < 2 {
+                    return;
+                }
+                // A wide character never straddles rows, it moves to the next one whole
+                let (row, col) = (self.cursor.row, self.cursor.col);
+                self.split_wide(row, col);
+                self.grid[row].cells[col] = self.blank_cell();
+                self.wrap();
+            }
+            if self.modes.insert {
+                self.insert_cells(width);
+            }
+
+            let (row, col) = (self.cursor.row, self.cursor.col);
+            self.split_wide(row, col);
+            if width == 2 {
+                self.split_wide(row, col + 1);
+            }
+            let attrs = self.cursor.attrs;
+            self.grid[row].cells[col] = Cell {
+                c,
+                combining: Vec::new(),
+                width: width as u8,
+                attrs,
+            };
+            if width == 2 {
+                self.grid[row].cells[col + 1] = Cell::spacer(attrs);
+            }
+
+            self.last_printed = Some(c);
+            if col + width >= self.cols {
+                self.cursor.col = self.cols - 1;
+                self.cursor.pending_wrap = true;
+            } else {
+                self.cursor.col += width;
+            }
+        }
+
+        /// Attaches a zero-width character to whatever was printed last.
+        fn combine(&mut self, c: char) {
+            let row = self.cursor.row;
+            let mut col = if self.cursor.pending_wrap {
+                self.cursor.col
+            } else if self.cursor.col > 0 {
+                self.cursor.col - 1
+            } else {
+                return;
+            };
+            if self.grid[row].cells[col].width == 0 && col > 0 {
+                col -= 1;
+            }
+            self.grid[row].cells[col].combining.push(c);
+        }
+
+        fn wrap(&mut self) {
+            self.grid[self.cursor.row].wrapped = true;
+            self.cursor.col = 0;
+            self.linefeed();
+        }
+
+        fn linefeed(&mut self) {
+            self.cursor.pending_wrap = false;
+            if self.cursor.row == self.scroll_bottom {
+                self.scroll_up(1);
+            } else if self.cursor.row + 1 < self.rows {
+                self.cursor.row += 1;
+            }
+        }
+
+        fn reverse_index(&mut self) {
+            self.cursor.pending_wrap = false;
+            if self.cursor.row == self.scroll_top {
+                self.scroll_down(1);
+            } else if self.cursor.row > 0 {
+                self.cursor.row -= 1;
+            }
+        }
+
+        fn scroll_up(&mut self, count: usize) {
+            let count = count.min(self.scroll_bottom - self.scroll_top + 1);
+            // Only rows leaving the top of the whole primary screen are kept, like xterm
+            let keep = self.scroll_top == 0 && !self.modes.alternate_screen;
+            for _ in 0..count {
+                let row = self.grid.remove(self.scroll_top);
+                if keep {
+                    self.lines_scrolled += 1;
+                    self.scrollback.push(row);
+                }
+                let blank = self.blank_row();
+                self.grid.insert(self.scroll_bottom, blank);
+            }
+        }
+
+        fn scroll_down(&mut self, count: usize) {
+            let count = count.min(self.scroll_bottom - self.scroll_top + 1);
+            for _ in 0..count {
+                self.grid.remove(self.scroll_bottom);
+                let blank = self.blank_row();
+                self.grid.insert(self.scroll_top, blank);
+            }
+        }
+
+        fn in_scroll_region(&self) -> bool {
+            self.cursor.row >= self.scroll_top && self.cursor.row <= self.scroll_bottom
+        }
+
+        fn insert_lines(&mut self, count: usize) {
+            if !self.in_scroll_region() {
+                return;
+            }
+            let count = count.min(self.scroll_bottom - self.cursor.row + 1);
+            for _ in 0..count {
+                self.grid.remove(self.scroll_bottom);
+                let blank = self.blank_row();
+                self.grid.insert(self.cursor.row, blank);
+            }
+            self.cursor.col = 0;
+            self.cursor.pending_wrap = false;
+        }
+
+        fn delete_lines(&mut self, count: usize) {
+            if !self.in_scroll_region() {
+                return;
+            }
+            let count = count.min(self.scroll_bottom - self.cursor.row + 1);
+            for _ in 0..count {
+                self.grid.remove(self.cursor.row);
+                let blank = self.blank_row();
+                self.grid.insert(self.scroll_bottom, blank);
+            }
+            self.cursor.col = 0;
+            self.cursor.pending_wrap = false;
+        }
+
+        fn insert_cells(&mut self, count: usize) {
+            let (row, col) = (self.cursor.row, self.cursor.col);
+            let count = count.min(self.cols - col);
+            self.split_wide(row, col);
+            let blank = self.blank_cell();
+            let cells = &mut self.grid[row].cells;
+            for _ in 0..count {
+                cells.insert(col, blank.clone());
+            }
+            cells.truncate(self.cols);
+            if cells.last().is_some_and(|cell| cell.width == 2) {
+                cells[self.cols - 1] = blank;
+            }
+        }
+
+        fn delete_cells(&mut self, count: usize) {
+            let (row, col) = (self.cursor.row, self.cursor.col);
+            let count = count.min(self.cols - col);
+            self.split_wide(row, col);
+            self.split_wide(row, col + count - 1);
+            let blank = self.blank_cell();
+            let cells = &mut self.grid[row].cells;
+            cells.drain(col..col + count);
+            cells.resize(self.cols, blank);
+            self.cursor.pending_wrap = false;
+        }
+
+        fn erase_cells(&mut self, row: usize, from: usize, to: usize) {
+            let to = to.min(self.cols);
+            if from >= to {
+                return;
+            }
+            self.split_wide(row, from);
+            self.split_wide(row, to - 1);
+            let blank = self.blank_cell();
+            for cell in &mut self.grid[row].cells[from..to] {
+                *cell = blank.clone();
+            }
+        }
+
+        fn erase_in_display(&mut self, mode: u16) {
+            let (row, col) = (self.cursor.row, self.cursor.col);
+            match mode {
+                0 => {
+                    self.erase_cells(row, self.erase_start(), self.cols);
+                    for below in row + 1..self.rows {
+                        self.grid[below] = self.blank_row();
+                    }
+                }
+                1 => {
+                    for above in 0..row {
+                        self.grid[above] = self.blank_row();
+                    }
+                    self.erase_cells(row, 0, col + 1);
+                }
+                2 => {
+                    for line in 0..self.rows {
+                        self.grid[line] = self.blank_row();
+                    }
+                }
+                3 => self.scrollback.clear(),
+                _ => (),
+            }
+            self.cursor.pending_wrap = false;
+        }
+
+        fn erase_in_line(&mut self, mode: u16) {
+            let (row, col) = (self.cursor.row, self.cursor.col);
+            match mode {
+                0 => self.erase_cells(row, self.erase_start(), self.cols),
+                1 => self.erase_cells(row, 0, col + 1),
+                2 => self.erase_cells(row, 0, self.cols),
+                _ => (),
+            }
+            if mode <= 2 {
+                self.grid[row].wrapped = false;
+            }
+        }
+
+        /// Where erasing to the end of the line starts. Once the last column has been written
+        /// the cursor is past it, so programs like top can fill a line and still clear the rest
+        /// of it. xterm.js and tmux keep that character, and so does this.
+        fn erase_start(&self) -> usize {
+            if self.cursor.pending_wrap {
+                self.cols
+            } else {
+                self.cursor.col
+            }
+        }
+
+        /// Moves the cursor to a 0-based position, relative to the scroll region in origin mode.
+        fn goto(&mut self, row: usize, col: usize) {
+            let (top, bottom) = if self.modes.origin {
+                (self.scroll_top, self.scroll_bottom)
+            } else {
+                (0, self.rows - 1)
+            };
+            self.cursor.row = (top + row).min(bottom);
+            self.cursor.col = col.min(self.cols - 1);
+            self.cursor.pending_wrap = false;
+        }
+
+        fn goto_row(&mut self, row: usize) {
+            self.goto(row, self.cursor.col);
+        }
+
+        fn goto_col(&mut self, col: usize) {
+            self.cursor.col = col.min(self.cols - 1);
+            self.cursor.pending_wrap = false;
+        }
+
+        fn move_up(&mut self, count: usize) {
+            let top = if self.cursor.row >= self.scroll_top { self.scroll_top } else { 0 };
+            self.cursor.row = self.cursor.row.saturating_sub(count).max(top);
+            self.cursor.pending_wrap = false;
+        }
+
+        fn move_down(&mut self, count: usize) {
+            let bottom = if self.cursor.row <= self.scroll_bottom {
+                self.scroll_bottom
+            } else {
+                self.rows - 1
+            };
+            self.cursor.row = (self.cursor.row + count).min(bottom);
+            self.cursor.pending_wrap = false;
+        }
+
+        fn tab_forward(&mut self, count: usize) {
+            for _ in 0..count {
+                let next = (self.cursor.col + 1..self.cols).find(|col| self.tabs[*col]);
+                self.cursor.col = next.unwrap_or(self.cols - 1);
+            }
+            self.cursor.pending_wrap = false;
+        }
+
+        fn tab_backward(&mut self, count: usize) {
+            for _ in 0..count {
+                let previous = (0..self.cursor.col).rev().find(|col| self.tabs[*col]);
+                self.cursor.col = previous.unwrap_or(0);
+            }
+            self.cursor.pending_wrap = false;
+        }
+
+        fn set_scroll_region(&mut self, top: usize, bottom: usize) {
+            let bottom = bottom.min(self.rows - 1);
+            if top < bottom {
+                self.scroll_top = top;
+                self.scroll_bottom = bottom;
+                self.goto(0, 0);
+            }
+        }
+
+        fn save_cursor(&mut self) {
+            self.saved = SavedCursor {
+                cursor: self.cursor,
+                origin: self.modes.origin,
+                charsets: self.charsets,
+            };
+        }
+
+        fn restore_cursor(&mut self) {
+            self.cursor = self.saved.cursor;
+            self.modes.origin = self.saved.origin;
+            self.charsets = self.saved.charsets;
+
+            // The scroll region may have changed since, and origin mode keeps the cursor inside it
+            let (top, bottom) = if self.modes.origin {
+                (self.scroll_top, self.scroll_bottom)
+            } else {
+                (0, self.rows - 1)
+            };
+            self.cursor.row = self.cursor.row.clamp(top, bottom);
+            self.cursor.col = self.cursor.col.min(self.cols - 1);
+        }
+
+        fn set_alternate_screen(&mut self, enabled: bool, save_cursor: bool) {
+            if enabled == self.modes.alternate_screen {
+                return;
+            }
+            if enabled && save_cursor {
+                self.save_cursor();
+            }
+            std::mem::swap(&mut self.grid, &mut self.inactive);
+            std::mem::swap(&mut self.saved, &mut self.inactive_saved);
+            self.modes.alternate_screen = enabled;
+            if enabled {
+                // The alternate screen always starts out blank
+                for row in 0..self.rows {
+                    self.grid[row] = self.blank_row();
+                }
+            } else if save_cursor {
+                self.restore_cursor();
+            }
+            self.cursor.pending_wrap = false;
+        }
+
+        fn set_mode(&mut self, private: bool, mode: u16, enabled: bool) {
+            match (private, mode) {
+                (false, 4) => self.modes.insert = enabled,
+                (true, 1) => self.modes.application_cursor = enabled,
+                (true, 6) => {
+                    self.modes.origin = enabled;
+                    self.goto(0, 0);
+                }
+                (true, 7) => self.modes.autowrap = enabled,
+                (true, 25) => self.modes.cursor_visible = enabled,
+                (true, 47) | (true, 1047) => self.set_alternate_screen(enabled, false),
+                (true, 1048) if enabled => self.save_cursor(),
+                (true, 1048) => self.restore_cursor(),
+                (true, 1049) => self.set_alternate_screen(enabled, true),
+                (true, 2004) => self.modes.bracketed_paste = enabled,
+                _ => (),
+            }
+        }
+
+        fn select_graphic_rendition(&mut self, params: &[&[u16]]) {
+            let attrs = &mut self.cursor.attrs;
+            if params.is_empty() {
+                *attrs = Attributes::default();
+                return;
+            }
+
+            let mut index = 0;
+            while index < params.len() {
+                let param = params[index];
+                match param[0] {
+                    0 => *attrs = Attributes::default(),
+                    1 => attrs.bold = true,
+                    2 => attrs.dim = true,
+                    3 => attrs.italic = true,
+                    4 => attrs.underline = param.get(1) != Some(&0),
+                    5 | 6 => attrs.blink = true,
+                    7 => attrs.inverse = true,
+                    8 => attrs.hidden = true,
+                    9 => attrs.strikethrough = true,
+                    21 => attrs.underline = true,
+                    22 => {
+                        attrs.bold = false;
+                        attrs.dim = false;
+                    }
+                    23 => attrs.italic = false,
+                    24 => attrs.underline = false,
+                    25 => attrs.blink = false,
+                    27 => attrs.inverse = false,
+                    28 => attrs.hidden = false,
+                    29 => attrs.strikethrough = false,
+                    code @ 30..=37 => attrs.foreground = Color::Indexed((code - 30) as u8),
+                    38 => {
+                        if let Some((color, used)) = extended_color(params, index) {
+                            attrs.foreground = color;
+                            index += used;
+                        }
+                    }
+                    39 => attrs.foreground = Color::Default,
+                    code @ 40..=47 => attrs.background = Color::Indexed((code - 40) as u8),
+                    48 => {
+                        if let Some((color, used)) = extended_color(params, index) {
+                            attrs.background = color;
+                            index += used;
+                        }
+                    }
+                    49 => attrs.background = Color::Default,
+                    code @ 90..=97 => attrs.foreground = Color::Indexed((code - 90 + 8) as u8),
+                    code @ 100..=107 => attrs.background = Color::Indexed((code - 100 + 8) as u8),
+                    _ => (),
+                }
+                index += 1;
+            }
+        }
+
+        fn reset(&mut self) {
+            let scrollback = std::mem::take(&mut self.scrollback);
+            let lines_scrolled = self.lines_scrolled;
+            let marks = std::mem::take(&mut self.marks);
+            let alerts = std::mem::take(&mut self.alerts);
+            *self = Screen::new(self.rows, self.cols, scrollback.limit.clone());
+            self.scrollback = scrollback;
+            self.lines_scrolled = lines_scrolled;
+            self.marks = marks;
+            self.alerts = alerts;
+        }
+
+        /// The cursor's position on the primary screen, as a line that survives scrolling.
+        fn cursor_line(&self) -> u64 {
+            self.lines_scrolled + self.cursor.row as u64
+        }
+
+        /// The primary screen's row at `line`, if it hasn't been dropped from the scrollback.
+        fn line(&self, line: u64) -> Option<&Row> {
+            let primary = if self.modes.alternate_screen { &self.inactive } else { &self.grid };
+            match line.checked_sub(self.lines_scrolled) {
+                Some(row) => primary.get(row as usize),
+                None => {
+                    let back = (self.lines_scrolled - line) as usize;
+                    let rows = &self.scrollback.rows;
+                    rows.len().checked_sub(back).and_then(|index| rows.get(index))
+                }
+            }
+        }
+
+        /// The text from `start` up to the cursor, joining rows that were wrapped.
+        fn text_since(&self, (start_line, start_col): (u64, usize)) -> String {
+            let end_line = self.cursor_line();
+            let mut text = String::new();
+            for line in start_line..=end_line {
+                let Some(row) = self.line(line) else {
+                    continue;
+                };
+                let from = if line == start_line { start_col } else { 0 };
+                let to = if line == end_line && !self.cursor.pending_wrap {
+                    self.cursor.col
+                } else {
+                    self.cols
+                };
+                text.push_str(&row.text_between(from, to));
+                if line != end_line && !row.wrapped {
+                    text.push('\n');
+                }
+            }
+            text.trim().to_string()
+        }
+
+        fn shell_mark(&mut self, kind: &[u8], args: &[&[u8]]) {
+            let line = self.cursor_line();
+            let mark = match kind.first() {
+                Some(b'A') => ShellMark::PromptStart { line },
+                Some(b'B') => {
+                    self.command_start = Some((line, self.cursor.col));
+                    ShellMark::CommandStart { line }
+                }
+                Some(b'C') => {
+                    let command = self
+                        .command_start
+                        .take()
+                        .map(|start| self.text_since(start))
+                        .unwrap_or_default();
+                    ShellMark::OutputStart { line, command }
+                }
+                Some(b'D') => ShellMark::CommandFinished {
+                    line,
+                    exit_code: args
+                        .first()
+                        .and_then(|code| std::str::from_utf8(code).ok())
+                        .and_then(|code| code.parse().ok()),
+                },
+                _ => return,
+            };
+            self.marks.push(mark);
+        }
+
+        /// The last `lines` lines of the primary screen and its scrollback as text and SGR
+        /// sequences, leaving out the blank rows below the last line of output.
+        pub fn tail(&self, lines: usize) -> String {
+            let primary = if self.modes.alternate_screen { &self.inactive } else { &self.grid };
+            let rows = self.scrollback.rows.iter().chain(primary.iter()).collect::<Vec<_>>();
+            let end = rows.iter().rposition(|row| !row.text().is_empty()).map_or(0, |last| last + 1);
+
+            let mut data = String::new();
+            let mut attrs = Attributes::default();
+            write_rows(&mut data, rows[end.saturating_sub(lines)..end].iter().copied(), &mut attrs);
+            if attrs != Attributes::default() {
+                data.push_str("\x1b[0m");
+            }
+            data
+        }
+
+        pub fn snapshot(&self) -> Snapshot {
+            let mut data = String::new();
+            let mut attrs = Attributes::default();
+            let (primary, primary_saved) = if self.modes.alternate_screen {
+                (&self.inactive, &self.inactive_saved)
+            } else {
+                (&self.grid, &self.saved)
+            };
+
+            let lines = self.scrollback.rows.iter().chain(primary.iter());
+            write_rows(&mut data, lines, &mut attrs);
+            if self.modes.alternate_screen {
+                let saved = primary_saved.cursor;
+                let _ = write!(data, "\x1b[0m\x1b[{};{}H\x1b[?1049h\x1b[H", saved.row + 1, saved.col + 1);
+                attrs = Attributes::default();
+                write_rows(&mut data, self.grid.iter(), &mut attrs);
+            }
+
+            if self.scroll_top != 0 || self.scroll_bottom != self.rows - 1 {
+                let _ = write!(data, "\x1b[{};{}r", self.scroll_top + 1, self.scroll_bottom + 1);
+            }
+            let row = if self.modes.origin {
+                data.push_str("\x1b[?6h");
+                self.cursor.row.saturating_sub(self.scroll_top)
+            } else {
+                self.cursor.row
+            };
+            let _ = write!(data, "\x1b[{};{}H", row + 1, self.cursor.col + 1);
+            data.push_str(&sgr(&self.cursor.attrs));
+            for (enabled, sequence) in [
+                (!self.modes.autowrap, "\x1b[?7l"),
+                (self.modes.insert, "\x1b[4h"),
+                (!self.modes.cursor_visible, "\x1b[?25l"),
+                (self.modes.application_cursor, "\x1b[?1h"),
+                (self.modes.bracketed_paste, "\x1b[?2004h"),
+                (self.charsets.designated[0] == Charset::DecGraphics, "\x1b(0"),
+                (self.charsets.designated[1] == Charset::DecGraphics, "\x1b)0"),
+                (self.charsets.shifted_out, "\x0e"),
+            ] {
+                if enabled {
+                    data.push_str(sequence);
+                }
+            }
+
+            Snapshot {
+                rows: self.rows,
+                cols: self.cols,
+                scrollback: self.scrollback.rows.len(),
+                cursor: self.cursor,
+                modes: self.modes,
+                data,
+            }
+        }
+    }
+
+    /// Writes rows as text and SGR sequences, leaving soft-wrapped rows to wrap by themselves.
+    fn write_rows<'a>(out: &mut String, rows: impl Iterator<Item = &'a Row>, attrs: &mut Attributes) {
+        let mut previous: Option<&Row> = None;
+        for row in rows {
+            if previous.is_some_and(|previous| !previous.wrapped) {
+                // Reset first so the new line isn't filled with the current background
+                if *attrs != Attributes::default() {
+                    out.push_str("\x1b[0m");
+                    *attrs = Attributes::default();
+                }
+                out.push_str("\r\n");
+            }
+            previous = Some(row);
+
+            let end = if row.wrapped {
+                row.cells.len()
+            } else {
+                row.cells
+                    .iter()
+                    .rposition(|cell| *cell != Cell::default())
+                    .map_or(0, |last| last + 1)
+            };
+            for cell in row.cells[..end].iter().filter(|cell| cell.width > 0) {
+                if cell.attrs != *attrs {
+                    out.push_str(&sgr(&cell.attrs));
+                    *attrs = cell.attrs;
+                }
+                out.push(cell.c);
+                out.extend(&cell.combining);
+            }
+        }
+    }
+
+    fn sgr(attrs: &Attributes) -> String {
+        let mut codes = vec!["0".to_string()];
+        for (enabled, code) in [
+            (attrs.bold, "1"),
+            (attrs.dim, "2"),
+            (attrs.italic, "3"),
+            (attrs.underline, "4"),
+            (attrs.blink, "5"),
+            (attrs.inverse, "7"),
+            (attrs.hidden, "8"),
+            (attrs.strikethrough, "9"),
+        ] {
+            if enabled {
+                codes.push(code.to_string());
+            }
+        }
+        for (color, base, extended) in [(attrs.foreground, 30, 38), (attrs.background, 40, 48)] {
+            match color {
+                Color::Default => (),
+                Color::Indexed(n) if n < 8 => codes.push((base + n as u16).to_string()),
+                Color::Indexed(n) if n < 16 => codes.push((base + 60 + n as u16 - 8).to_string()),
+                Color::Indexed(n) => codes.push(format!("{};5;{}", extended, n)),
+                Color::Rgb(r, g, b) => codes.push(format!("{};2;{};{};{}", extended, r, g, b)),
+            }
+        }
+        format!("\x1b[{}m", codes.join(";"))
+    }
+
+    /// Parses the color following a 38/48 at `index`, in either the `38:2:r:g:b` or the
+    /// `38;2;r;g;b` form. Returns the color and how many extra parameters it used up.
+    fn extended_color(params: &[&[u16]], index: usize) -> Option<(Color, usize)> {
+        let param = params[index];
+        if param.len() > 1 {
+            // Colon separated subparameters, possibly with a color space ID before the RGB values
+            return match param[1] {
+                5 => param.get(2).map(|n| (Color::Indexed(*n as u8), 0)),
+                2 => {
+                    let rgb = &param[2..];
+                    let rgb = if rgb.len() >= 4 { &rgb[1..] } else { rgb };
+                    match rgb {
+                        [r, g, b, ..] => Some((Color::Rgb(*r as u8, *g as u8, *b as u8), 0)),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+        }
+
+        let next = |offset: usize| params.get(index + offset).map(|p| p[0]);
+        match next(1)? {
+            5 => Some((Color::Indexed(next(2)? as u8), 2)),
+            2 => Some((
+                Color::Rgb(next(2)? as u8, next(3)? as u8, next(4)? as u8),
+                4,
+            )),
+            _ => None,
+        }
+    }
+
+    /// The path of a `file://host/path` URL, percent-decoded. The host is ignored since the
+    /// shell runs on this machine, remote shells report their own host's paths anyway.
+    fn file_url_path(url: &str) -> Option<String> {
+        let rest = url.strip_prefix("file://")?;
+        let path = &rest[rest.find('/')?..];
+
+        let mut bytes = Vec::with_capacity(path.len());
+        let mut chars = path.bytes();
+        while let Some(byte) = chars.next() {
+            if byte == b'%' {
+                let hex = [chars.next()?, chars.next()?];
+                let hex = std::str::from_utf8(&hex).ok()?;
+                bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            } else {
+                bytes.push(byte);
+            }
+        }
+        String::from_utf8(bytes).ok()
+    }
+
+    /// The parameter at `index`, where a missing or zero value means `default`.
+    fn arg(params: &[&[u16]], index: usize, default: usize) -> usize {
+        match params.get(index).map(|p| p[0]) {
+            Some(0) | None => default,
+            Some(value) => value as usize,
+        }
+    }
+
+    fn raw_arg(params: &[&[u16]], index: usize) -> u16 {
+        params.get(index).map(|p| p[0]).unwrap_or(0)
+    }
+
+    impl Perform for Screen {
+        fn print(&mut self, c: char) {
+            self.print_char(self.charsets.map(c));
+        }
+
+        fn execute(&mut self, byte: u8) {
+            match byte {
+                0x07 => self.alerts.push(Alert::Bell),
+                0x08 => {
+                    self.cursor.col = self.cursor.col.saturating_sub(1);
+                    self.cursor.pending_wrap = false;
+                }
+                0x09 => self.tab_forward(1),
+                0x0a..=0x0c => self.linefeed(),
+                0x0d => {
+                    self.cursor.col = 0;
+                    self.cursor.pending_wrap = false;
+                }
+                0x0e => self.charsets.shifted_out = true,
+                0x0f => self.charsets.shifted_out = false,
+                _ => (),
+            }
+        }
+
+        fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], ignore: bool, action: char) {
+            if ignore {
+                return;
+            }
+            let params = params.iter().collect::<Vec<_>>();
+            let params = params.as_slice();
+            let private = intermediates.first() == Some(&b'?');
+
+            match (action, intermediates) {
+                ('@', []) => self.insert_cells(arg(params, 0, 1)),
+                ('A', []) => self.move_up(arg(params, 0, 1)),
+                ('B', []) | ('e', []) => self.move_down(arg(params, 0, 1)),
+                ('C', []) | ('a', []) => self.goto_col(self.cursor.col + arg(params, 0, 1)),
+                ('D', []) => self.goto_col(self.cursor.col.saturating_sub(arg(params, 0, 1))),
+                ('E', []) => {
+                    self.move_down(arg(params, 0, 1));
+                    self.cursor.col = 0;
+                }
+                ('F', []) => {
+                    self.move_up(arg(params, 0, 1));
+                    self.cursor.col = 0;
+                }
+                ('G', []) | ('`', []) => self.goto_col(arg(params, 0, 1) - 1),
+                ('H', []) | ('f', []) => self.goto(arg(params, 0, 1) - 1, arg(params, 1, 1) - 1),
+                ('I', []) => self.tab_forward(arg(params, 0, 1)),
+                ('J', []) => self.erase_in_display(raw_arg(params, 0)),
+                ('K', []) => self.erase_in_line(raw_arg(params, 0)),
+                ('L', []) => self.insert_lines(arg(params, 0, 1)),
+                ('M', []) => self.delete_lines(arg(params, 0, 1)),
+                ('P', []) => self.delete_cells(arg(params, 0, 1)),
+                ('S', []) => self.scroll_up(arg(params, 0, 1)),
+                ('T', []) => self.scroll_down(arg(params, 0, 1)),
+                ('X', []) => {
+                    let (row, col) = (self.cursor.row, self.cursor.col);
+                    self.erase_cells(row, col, col + arg(params, 0, 1));
+                    self.cursor.pending_wrap = false;
+                }
+                ('Z', []) => self.tab_backward(arg(params, 0, 1)),
+                ('b', []) => {
+                    if let Some(c) = self.last_printed {
+                        for _ in 0..arg(params, 0, 1).min(self.rows * self.cols) {
+                            self.print_char(c);
+                        }
+                    }
+                }
+                ('d', []) => self.goto_row(arg(params, 0, 1) - 1),
+                ('g', []) => match raw_arg(params, 0) {
+                    0 => self.tabs[self.cursor.col] = false,
+                    3 => self.tabs.fill(false),
+                    _ => (),
+                },
+                ('h', _) | ('l', _) => {
+                    for param in params {
+                        self.set_mode(private, param[0], action == 'h');
+                    }
+                }
+                ('m', []) => self.select_graphic_rendition(params),
+                ('r', []) => self.set_scroll_region(arg(params, 0, 1) - 1, arg(params, 1, self.rows) - 1),
+                ('s', []) => self.save_cursor(),
+                ('u', []) => self.restore_cursor(),
+                _ => (),
+            }
+        }
+
+        fn osc_dispatch(&mut self, params: &[&[u8]], _bell_terminated: bool) {
+            let Some((&kind, rest)) = params.split_first() else {
+                return;
+            };
+            if kind == b"133" {
+                if let Some((mark, args)) = rest.split_first() {
+                    self.shell_mark(mark, args);
+                }
+                return;
+            }
+            // The text may itself contain semicolons, which the parser split on
+            let text = rest
+                .iter()
+                .map(|part| String::from_utf8_lossy(part))
+                .collect::<Vec<_>>()
+                .join(";");
+            let text = Some(text).filter(|text| !text.is_empty());
+
+            match kind {
+                b"0" => {
+                    self.title = text.clone();
+                    self.icon_name = text;
+                }
+                b"1" => self.icon_name = text,
+                b"2" => self.title = text,
+                b"7" => {
+                    if let Some(path) = text.as_deref().and_then(file_url_path) {
+                        self.working_directory = Some(path);
+                    }
+                }
+                // ConEmu uses OSC 9 with a numeric first parameter for other things entirely
+                b"9" if !rest.first().is_some_and(|first| first.iter().all(u8::is_ascii_digit)) => {
+                    if let Some(body) = text {
+                        self.alerts.push(Alert::Notification { title: None, body });
+                    }
+                }
+                b"777" if rest.first() == Some(&&b"notify"[..]) => {
+                    let title = rest.get(1).map(|title| String::from_utf8_lossy(title).to_string());
+                    let body = rest
+                        .get(2..)
+                        .unwrap_or_default()
+                        .iter()
+                        .map(|part| String::from_utf8_lossy(part))
+                        .collect::<Vec<_>>()
+                        .join(";");
+                    self.alerts.push(Alert::Notification {
+                        title: title.filter(|title| !title.is_empty()),
+                        body,
+                    });
+                }
+                _ => (),
+            }
+        }
+
+        fn esc_dispatch(&mut self, intermediates: &[u8], ignore: bool, byte: u8) {
+            if ignore {
+                return;
+            }
+            match (byte, intermediates) {
+                (b'7', []) => self.save_cursor(),
+                (b'8', []) => self.restore_cursor(),
+                (b'8', [b'#']) => {
+                    // DECALN fills the screen with E's for alignment tests
+                    for row in self.grid.iter_mut() {
+                        for cell in row.cells.iter_mut() {
+                            *cell = Cell {
+                                c: 'E',
+                                ..Cell::default()
+                            };
+                        }
+                    }
+                    self.scroll_top = 0;
+                    self.scroll_bottom = self.rows - 1;
+                    self.goto(0, 0);
+                }
+                (b'D', []) => self.linefeed(),
+                (b'E', []) => {
+                    self.cursor.col = 0;
+                    self.linefeed();
+                }
+                (b'H', []) => self.tabs[self.cursor.col] = true,
+                (b'M', []) => self.reverse_index(),
+                (b'c', []) => self.reset(),
+                (charset, [designate @ (b'(' | b')')]) => {
+                    let charset = if charset == b'0' { Charset::DecGraphics } else { Charset::Ascii };
+                    self.charsets.designated[usize::from(*designate == b')')] = charset;
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// A [`Screen`] together with the parser that turns PTY output into updates to it.
+    pub struct VirtualTerminal {
+        parser: Parser,
+        screen: Screen,
+    }
+
+    impl VirtualTerminal {
+        pub fn new(size: PtySize, scrollback: ScrollbackConfig) -> Self {
+            VirtualTerminal {
+                parser: Parser::new(),
+                screen: Screen::new(size.rows as usize, size.cols as usize, scrollback),
+            }
+        }
+
+        pub fn feed(&mut self, bytes: &[u8]) {
+            for byte in bytes {
+                self.parser.advance(&mut self.screen, *byte);
+            }
+        }
+
+        pub fn resize(&mut self, size: PtySize) {
+            self.screen.resize(size.rows as usize, size.cols as usize);
+        }
+
+        /// Starts over with a blank screen and no scrollback.
+        pub fn clear(&mut self, size: PtySize) {
+            let limit = self.screen.scrollback.limit.clone();
+            self.parser = Parser::new();
+            self.screen = Screen::new(size.rows as usize, size.cols as usize, limit);
+        }
+
+        pub fn set_scrollback(&mut self, limit: ScrollbackConfig) {
+            self.screen.scrollback.set_limit(limit);
+        }
+
+        pub fn screen(&self) -> &Screen {
+            &self.screen
+        }
+
+        pub fn take_marks(&mut self) -> Vec<ShellMark> {
+            self.screen.take_marks()
+        }
+
+        pub fn take_alerts(&mut self) -> Vec<Alert> {
+            self.screen.take_alerts()
+        }
+    }
+
+    impl fmt::Debug for VirtualTerminal {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("VirtualTerminal")
+                .field("screen", &self.screen)
+                .finish_non_exhaustive()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::{fs, path::PathBuf};
+
+        use super::*;
+
+        fn size(rows: u16, cols: u16) -> PtySize {
+            PtySize {
+                rows,
+                cols,
+                pixel_width: 0,
+                pixel_height: 0,
+            }
+        }
+
+        /// Feeds `tests/fixtures/emulator/{name}.vt` to a terminal and checks the result against
+        /// `{name}.txt`: a `rows cols cursor_row cursor_col` line, then the expected screen text.
+        fn fixture(name: &str) -> VirtualTerminal {
+            let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/emulator");
+            let input = fs::read(dir.join(format!("{}.vt", name))).unwrap();
+            let expected = fs::read_to_string(dir.join(format!("{}.txt", name))).unwrap();
+            let (header, text) = expected.split_once('\n').unwrap();
+            let header = header
+                .split_whitespace()
+                .map(|n| n.parse().unwrap())
+                .collect::<Vec<usize>>();
+            let [rows, cols, row, col] = header[..] else {
+                panic!("{}.txt should start with `rows cols cursor_row cursor_col`", name);
+            };
+
+            let mut terminal = VirtualTerminal::new(size(rows as u16, cols as u16), ScrollbackConfig::default());
+            terminal.feed(&input);
+            let screen = terminal.screen();
+            assert_eq!(screen.text().trim_end(), text.trim_end(), "screen of {}", name);
+            assert_eq!((screen.cursor().row, screen.cursor().col), (row, col), "cursor of {}", name);
+            terminal
+        }
+
+        /// A terminal of the same size that's been fed the snapshot should look the same.
+        fn assert_snapshot_restores(terminal: &VirtualTerminal) {
+            let screen = terminal.screen();
+            let snapshot = screen.snapshot();
+            let mut restored = VirtualTerminal::new(size(snapshot.rows as u16, snapshot.cols as u16), ScrollbackConfig::default());
+            restored.feed(snapshot.data.as_bytes());
+            assert_eq!(restored.screen().rows(), screen.rows());
+            assert_eq!(restored.screen().cursor(), screen.cursor());
+            assert_eq!(restored.screen().modes(), screen.modes());
+        }
+
+        #[test]
+        fn cursor_movement() {
+            let terminal = fixture("cursor");
+            assert!(!terminal.screen().cursor().pending_wrap);
+            assert_snapshot_restores(&terminal);
+        }
+
+        #[test]
+        fn select_graphic_rendition() {
+            let terminal = fixture("sgr");
+            let rows = terminal.screen().rows();
+            let attrs = |row: usize, col: usize| rows[row].cells[col].attrs;
+
+            assert!(attrs(0, 0).bold);
+            assert_eq!(attrs(0, 0).foreground, Color::Indexed(1));
+            assert_eq!(attrs(0, 1), Attributes::default());
+            assert_eq!(attrs(0, 2).foreground, Color::Indexed(200));
+            assert_eq!(attrs(0, 3).foreground, Color::Rgb(10, 20, 30));
+            assert_eq!(attrs(0, 4).background, Color::Rgb(1, 2, 3));
+            assert!(attrs(0, 5).inverse && attrs(0, 5).underline);
+            assert_eq!(attrs(0, 6), Attributes::default());
+            assert_eq!(attrs(1, 0).foreground, Color::Indexed(10));
+            assert_eq!(attrs(1, 0).background, Color::Indexed(12));
+
+            // Erasing keeps the background color that was current at the time
+            assert!(rows[2].cells.iter().all(|cell| cell.attrs.background == Color::Indexed(4)));
+            assert_eq!(terminal.screen().cursor().attrs, Attributes::default());
+            assert_snapshot_restores(&terminal);
+        }
+
+        #[test]
+        fn alternate_screen() {
+            let mut terminal = fixture("alternate_screen");
+            assert!(terminal.screen().modes().alternate_screen);
+            assert_snapshot_restores(&terminal);
+
+            // Nothing scrolled off the alternate screen ends up in the scrollback
+            terminal.feed(b"\n\n\n\n\n\n");
+            assert_eq!(terminal.screen().snapshot().scrollback, 0);
+
+            terminal.feed(b"\x1b[?1049l");
+            let screen = terminal.screen();
+            assert!(!screen.modes().alternate_screen);
+            assert_eq!(screen.text().trim_end(), "primary\nline2");
+            assert_eq!((screen.cursor().row, screen.cursor().col), (1, 5));
+        }
+
+        #[test]
+        fn scroll_region() {
+            let terminal = fixture("scroll_region");
+
+            // Only the row that left the top of the whole screen is kept
+            assert_eq!(terminal.screen().snapshot().scrollback, 1);
+            assert_snapshot_restores(&terminal);
+        }
+
+        #[test]
+        fn origin_mode_cursor_restored_into_new_scroll_region() {
+            let mut terminal = VirtualTerminal::new(size(24, 80), ScrollbackConfig::default());
+            terminal.feed(b"\x1b[?6h\x1b7\x1b[5;20r\x1b8");
+            assert_eq!(terminal.screen().cursor().row, 4);
+            assert_snapshot_restores(&terminal);
+        }
+
+        #[test]
+        fn wide_characters() {
+            let terminal = fixture("wide_chars");
+            let rows = terminal.screen().rows();
+
+            assert_eq!((rows[0].cells[4].width, rows[0].cells[5].width), (2, 0));
+            assert!(rows[0].wrapped);
+
+            // Overwriting the second half of 中 blanked the first
+            assert_eq!((rows[0].cells[1].c, rows[0].cells[1].width), (' ', 1));
+
+            // 文 didn't fit in the last column, so it moved to the next row whole
+            assert_eq!((rows[1].cells[5].c, rows[1].cells[5].width), (' ', 1));
+            assert!(rows[1].wrapped);
+            assert_eq!(rows[2].cells[2].combining, vec!['\u{301}']);
+            assert_snapshot_restores(&terminal);
+        }
+
+        /// Output captured from the real programs in a tmux pane, which gave the expected screens.
+        #[test]
+        fn real_programs() {
+            for name in ["vim", "less", "git_log", "top", "ncurses_box"] {
+                let terminal = fixture(name);
+                assert_snapshot_restores(&terminal);
+            }
+        }
+
+        #[test]
+        fn dec_special_graphics() {
+            let mut terminal = VirtualTerminal::new(size(4, 10), ScrollbackConfig::default());
+            // G0 designated directly, G1 shifted in and out, and ASCII outside the range kept
+            terminal.feed(b"\x1b(0lqk\x1b(B lqk\r\n\x1b)0x\x0ex1\x0fx\r\n");
+            // Saving the cursor saves the character sets with it
+            terminal.feed(b"\x1b(0\x1b7\x1b(Bq\x1b8q");
+            let rows = terminal.screen().rows();
+            assert_eq!(rows[0].text(), "┌─┐ lqk");
+            assert_eq!(rows[1].text(), "x│1x");
+            assert_eq!(rows[2].text(), "─");
+
+            // A new subscriber's terminal carries on drawing where this one is
+            let snapshot = terminal.screen().snapshot();
+            let mut restored = VirtualTerminal::new(size(4, 10), ScrollbackConfig::default());
+            restored.feed(snapshot.data.as_bytes());
+            restored.feed(b"j");
+            assert_eq!(restored.screen().rows()[2].text(), "─┘");
+        }
+
+        fn alerts(input: &[u8]) -> Vec<Alert> {
+            let mut terminal = VirtualTerminal::new(size(24, 80), ScrollbackConfig::default());
+            terminal.feed(input);
+            terminal.take_alerts()
+        }
+
+        fn notification(title: Option<&str>, body: &str) -> Alert {
+            Alert::Notification {
+                title: title.map(str::to_string),
+                body: body.to_string(),
+            }
+        }
+
+        #[test]
+        fn osc_9_notifications() {
+            assert_eq!(alerts(b"\x1b]9;Build done\x07"), vec![notification(None, "Build done")]);
+            assert_eq!(alerts(b"\x1b]9;a;b\x1b\\"), vec![notification(None, "a;b")]);
+
+            // ConEmu's progress reports and the like aren't notifications
+            assert_eq!(alerts(b"\x1b]9;4;1;50\x07"), vec![]);
+            assert_eq!(alerts(b"\x1b]9;\x07"), vec![]);
+        }
+
+        #[test]
+        fn osc_777_notifications() {
+            assert_eq!(
+                alerts(b"\x1b]777;notify;Tests;3 failed; 2 skipped\x07"),
+                vec![notification(Some("Tests"), "3 failed; 2 skipped")]
+            );
+            assert_eq!(alerts(b"\x1b]777;notify;;Done\x07"), vec![notification(None, "Done")]);
+            assert_eq!(alerts(b"\x1b]777;notify\x07"), vec![notification(None, "")]);
+            assert_eq!(alerts(b"\x1b]777;preexec;ls\x07"), vec![]);
+        }
+
+        #[test]
+        fn bell() {
+            assert_eq!(alerts(b"ding\x07"), vec![Alert::Bell]);
+        }
+
+        #[test]
+        fn lowering_the_scrollback_limit() {
+            let mut terminal = VirtualTerminal::new(size(2, 10), ScrollbackConfig::default());
+            for line in 0..10 {
+                terminal.feed(format!("{}\r\n", line).as_bytes());
+            }
+            assert_eq!(terminal.screen().snapshot().scrollback, 9);
+
+            // The oldest lines go, and later ones stay within the new limit
+            terminal.set_scrollback(ScrollbackConfig {
+                lines: 3,
+                ..ScrollbackConfig::default()
+            });
+            assert_eq!(terminal.screen().snapshot().scrollback, 3);
+            assert_eq!(terminal.screen().scrollback.rows[0].text().trim_end(), "6");
+            terminal.feed(b"10\r\n");
+            assert_eq!(terminal.screen().snapshot().scrollback, 3);
+        }
+    }
+}
@@ -0,0 +1,225 @@
+pub mod error {
+    use std::{io, path::PathBuf};
+
+    use serde::{Deserialize, Serialize, Serializer};
+    use serde_json::{json, Value};
+    use snafu::Snafu;
+    use uuid::Uuid;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub))]
+    pub enum TartError {
+        #[snafu(display("Failed to open a PTY: {reason}"))]
+        PtyOpen { reason: String },
+
+        #[snafu(display("Failed to spawn {command:?}: {source}"))]
+        Spawn { command: String, source: io::Error },
+
+        #[snafu(display("Failed to get the PTY's IO handles: {reason}"))]
+        PtyHandles { reason: String },
+
+        #[snafu(display("Failed to write to terminal {id}: {source}"))]
+        Write { id: Uuid, source: io::Error },
+
+        #[snafu(display("Failed to resize terminal {id}: {reason}"))]
+        Resize { id: Uuid, reason: String },
+
+        #[snafu(display("Unknown terminal ID {id}"))]
+        UnknownTerminal { id: Uuid },
+
+        #[snafu(display("Terminal {id} is no longer accepting commands"))]
+        TerminalClosed { id: Uuid },
+
+        #[snafu(display("The {resource} lock was poisoned"))]
+        LockPoisoned { resource: String },
+
+        #[snafu(display("Failed to serialize {what}: {source}"))]
+        Serialization { what: String, source: serde_json::Error },
+
+        #[snafu(display("Unknown profile {name:?}"))]
+        UnknownProfile { name: String },
+
+        #[snafu(display("Profile {name:?} already exists"))]
+        ProfileExists { name: String },
+
+        #[snafu(display("Flow control needs 0 < low_watermark <= high_watermark, got {low} and {high}"))]
+        InvalidFlowControl { high: usize, low: usize },
+
+        #[snafu(display("Failed to read {}: {source}", path.display()))]
+        ReadFile { path: PathBuf, source: io::Error },
+
+        #[snafu(display("Failed to write {}: {source}", path.display()))]
+        WriteFile { path: PathBuf, source: io::Error },
+
+        #[snafu(display("Failed to parse {}: {source}", path.display()))]
+        ParseToml {
+            path: PathBuf,
+            #[snafu(source(from(toml::de::Error, Box::new)))]
+            source: Box<toml::de::Error>,
+        },
+
+        #[snafu(display("{} can't be changed until it loads again: {reason}", path.display()))]
+        ProfilesUnreadable { path: PathBuf, reason: String },
+
+        #[snafu(display("Failed to serialize TOML: {source}"))]
+        SerializeToml { source: toml::ser::Error },
+
+        #[snafu(display("{} is not a valid asciicast file: {reason}", path.display()))]
+        ParseCast { path: PathBuf, reason: String },
+
+        #[snafu(display("Terminal {id} is not a playback"))]
+        NotPlayback { id: Uuid },
+
+        #[snafu(display("Playback speed must be between 0.01 and 100, got {speed}"))]
+        InvalidSpeed { speed: f64 },
+
+        #[snafu(display("Idle time limit must be a number of seconds, zero or more, got {limit}"))]
+        InvalidIdleTimeLimit { limit: f64 },
+
+        #[snafu(display("Failed to parse the saved session {}: {source}", path.display()))]
+        ParseSession { path: PathBuf, source: serde_json::Error },
+
+        #[snafu(display("Failed to use the socket {}: {source}", path.display()))]
+        Socket { path: PathBuf, source: io::Error },
+
+        #[snafu(display("Something is already listening on {}", path.display()))]
+        AlreadyRunning { path: PathBuf },
+
+        #[snafu(display("Lost the connection to tartd"))]
+        DaemonDisconnected,
+
+        #[snafu(display("tart isn't running"))]
+        NotRunning,
+
+        #[snafu(display("{reason}"))]
+        InvalidArguments { reason: String },
+
+        #[snafu(display("The command failed to run: {reason}"))]
+        CommandFailed { reason: String },
+    }
+
+    impl TartError {
+        pub fn kind(&self) -> &'static str {
+            match self {
+                TartError::PtyOpen { .. } => "pty_open",
+                TartError::Spawn { .. } => "spawn",
+                TartError::PtyHandles { .. } => "pty_handles",
+                TartError::Write { .. } => "write",
+                TartError::Resize { .. } => "resize",
+                TartError::UnknownTerminal { .. } => "unknown_terminal",
+                TartError::TerminalClosed { .. } => "terminal_closed",
+                TartError::LockPoisoned { .. } => "lock_poisoned",
+                TartError::Serialization { .. } => "serialization",
+                TartError::UnknownProfile { .. } => "unknown_profile",
+                TartError::ProfileExists { .. } => "profile_exists",
+                TartError::InvalidFlowControl { .. } => "invalid_flow_control",
+                TartError::ReadFile { .. } => "read_file",
+                TartError::WriteFile { .. } => "write_file",
+                TartError::ParseToml { .. } => "parse_toml",
+                TartError::ProfilesUnreadable { .. } => "profiles_unreadable",
+                TartError::SerializeToml { .. } => "serialize_toml",
+                TartError::ParseCast { .. } => "parse_cast",
+                TartError::NotPlayback { .. } => "not_playback",
+                TartError::InvalidSpeed { .. } => "invalid_speed",
+                TartError::InvalidIdleTimeLimit { .. } => "invalid_idle_time_limit",
+                TartError::ParseSession { .. } => "parse_session",
+                TartError::Socket { .. } => "socket",
+                TartError::AlreadyRunning { .. } => "already_running",
+                TartError::DaemonDisconnected => "daemon_disconnected",
+                TartError::NotRunning => "not_running",
+                TartError::InvalidArguments { .. } => "invalid_arguments",
+                TartError::CommandFailed { .. } => "command_failed",
+            }
+        }
+
+        /// The variant's fields as JSON, with sources flattened to their message (and IO error kind).
+        pub fn details(&self) -> Value {
+            match self {
+                TartError::PtyOpen { reason }
+                | TartError::PtyHandles { reason }
+                | TartError::InvalidArguments { reason }
+                | TartError::CommandFailed { reason } => {
+                    json!({ "reason": reason })
+                }
+                TartError::Spawn { command, source } => json!({
+                    "command": command,
+                    "source": source.to_string(),
+                    "io_kind": format!("{:?}", source.kind()),
+                }),
+                TartError::Write { id, source } => json!({
+                    "id": id,
+                    "source": source.to_string(),
+                    "io_kind": format!("{:?}", source.kind()),
+                }),
+                TartError::Resize { id, reason } => json!({ "id": id, "reason": reason }),
+                TartError::UnknownTerminal { id }
+                | TartError::TerminalClosed { id }
+                | TartError::NotPlayback { id } => json!({ "id": id }),
+                TartError::LockPoisoned { resource } => json!({ "resource": resource }),
+                TartError::Serialization { what, source } => {
+                    json!({ "what": what, "source": source.to_string() })
+                }
+                TartError::UnknownProfile { name } | TartError::ProfileExists { name } => {
+                    json!({ "name": name })
+                }
+                TartError::InvalidFlowControl { high, low } => json!({ "high": high, "low": low }),
+                TartError::ReadFile { path, source }
+                | TartError::WriteFile { path, source }
+                | TartError::Socket { path, source } => {
+                    json!({
+                        "path": path,
+                        "source": source.to_string(),
+                        "io_kind": format!("{:?}", source.kind()),
+                    })
+                }
+                TartError::ParseToml { path, source } => json!({
+                    "path": path,
+                    "source": source.message(),
+                    "span": source.span().map(|span| [span.start, span.end]),
+                }),
+                TartError::SerializeToml { source } => json!({ "source": source.to_string() }),
+                TartError::ParseCast { path, reason } | TartError::ProfilesUnreadable { path, reason } => {
+                    json!({ "path": path, "reason": reason })
+                }
+                TartError::InvalidSpeed { speed } => json!({ "speed": speed }),
+                TartError::InvalidIdleTimeLimit { limit } => json!({ "limit": limit }),
+                TartError::ParseSession { path, source } => json!({
+                    "path": path,
+                    "source": source.to_string(),
+                    "line": source.line(),
+                    "column": source.column(),
+                }),
+                TartError::AlreadyRunning { path } => json!({ "path": path }),
+                TartError::DaemonDisconnected | TartError::NotRunning => json!({}),
+            }
+        }
+
+        pub fn payload(&self) -> ErrorPayload {
+            ErrorPayload {
+                kind: self.kind().to_string(),
+                message: self.to_string(),
+                details: self.details(),
+            }
+        }
+    }
+
+    /// The shape errors take on their way to the frontend.
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct ErrorPayload {
+        pub kind: String,
+        pub message: String,
+        pub details: Value,
+    }
+
+    impl From<&TartError> for ErrorPayload {
+        fn from(error: &TartError) -> Self {
+            error.payload()
+        }
+    }
+
+    impl Serialize for TartError {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            self.payload().serialize(serializer)
+        }
+    }
+}
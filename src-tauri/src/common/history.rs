@@ -0,0 +1,177 @@
+pub mod command_history {
+    use std::{
+        collections::VecDeque,
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::common::screen::ShellMark;
+
+    /// How many finished commands are remembered per terminal.
+    const MAX_COMMANDS: usize = 1000;
+
+    /// One prompt and the command run from it, pieced together from OSC 133 marks.
+    #[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+    pub struct CommandRecord {
+        pub prompt_line: u64,
+        pub command: Option<String>,
+
+        /// Milliseconds since the Unix epoch
+        pub started_at: Option<u64>,
+        pub finished_at: Option<u64>,
+        pub exit_code: Option<i32>,
+
+        /// The lines the command's output occupies, `output_end` being exclusive
+        pub output_start: Option<u64>,
+        pub output_end: Option<u64>,
+    }
+
+    impl CommandRecord {
+        /// How long the command ran for in milliseconds, once it has finished.
+        pub fn duration(&self) -> Option<u64> {
+            Some(self.finished_at?.saturating_sub(self.started_at?))
+        }
+    }
+
+    #[derive(Clone, Debug, Default)]
+    pub struct CommandHistory {
+        finished: VecDeque<CommandRecord>,
+
+        /// The prompt currently being shown, or the command running from it
+        current: Option<CommandRecord>,
+    }
+
+    impl CommandHistory {
+        /// Advances the history by one mark, returning the command it finished if any.
+        pub fn apply(&mut self, mark: ShellMark) -> Option<CommandRecord> {
+            match mark {
+                ShellMark::PromptStart { line } => {
+                    // A prompt that never ran anything (e.g. ^C) is simply replaced
+                    self.current = Some(CommandRecord {
+                        prompt_line: line,
+                        ..CommandRecord::default()
+                    });
+                    None
+                }
+                ShellMark::CommandStart { .. } => None,
+                ShellMark::OutputStart { line, command } => {
+                    let current = self.current.get_or_insert_with(|| CommandRecord {
+                        prompt_line: line,
+                        ..CommandRecord::default()
+                    });
+                    current.command = Some(command);
+                    current.started_at = Some(now());
+                    current.output_start = Some(line);
+                    None
+                }
+                ShellMark::CommandFinished { line, exit_code } => {
+                    let mut record = self.current.take()?;
+                    // Nothing ran, e.g. the prompt was left with an empty line
+                    record.started_at?;
+                    record.finished_at = Some(now());
+                    record.exit_code = exit_code;
+                    record.output_end = Some(line);
+
+                    if self.finished.len() == MAX_COMMANDS {
+                        self.finished.pop_front();
+                    }
+                    self.finished.push_back(record.clone());
+                    Some(record)
+                }
+            }
+        }
+
+        /// Every remembered command, oldest first, followed by the one still running if any.
+        pub fn list(&self) -> Vec<CommandRecord> {
+            let running = self.current.iter().filter(|record| record.started_at.is_some());
+            self.finished.iter().chain(running).cloned().collect()
+        }
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn run(history: &mut CommandHistory, line: u64, command: &str, exit_code: i32) -> Option<CommandRecord> {
+            history.apply(ShellMark::PromptStart { line });
+            history.apply(ShellMark::CommandStart { line });
+            history.apply(ShellMark::OutputStart {
+                line: line + 1,
+                command: command.to_string(),
+            });
+            history.apply(ShellMark::CommandFinished {
+                line: line + 3,
+                exit_code: Some(exit_code),
+            })
+        }
+
+        #[test]
+        fn marks_make_a_record() {
+            let mut history = CommandHistory::default();
+            let record = run(&mut history, 4, "ls", 2).unwrap();
+            assert_eq!(record.prompt_line, 4);
+            assert_eq!(record.command.as_deref(), Some("ls"));
+            assert_eq!(record.exit_code, Some(2));
+            assert_eq!((record.output_start, record.output_end), (Some(5), Some(7)));
+            assert!(record.duration().is_some());
+            assert_eq!(history.list(), vec![record]);
+        }
+
+        #[test]
+        fn prompts_that_ran_nothing_are_left_out() {
+            let mut history = CommandHistory::default();
+
+            // ^C at the prompt, then an empty line
+            history.apply(ShellMark::PromptStart { line: 0 });
+            history.apply(ShellMark::PromptStart { line: 1 });
+            let finished = history.apply(ShellMark::CommandFinished {
+                line: 2,
+                exit_code: Some(130),
+            });
+            assert_eq!(finished, None);
+
+            // A finish without any prompt, e.g. integration loaded mid-command
+            let finished = history.apply(ShellMark::CommandFinished {
+                line: 2,
+                exit_code: None,
+            });
+            assert_eq!(finished, None);
+            assert!(history.list().is_empty());
+        }
+
+        #[test]
+        fn running_command_is_listed_last() {
+            let mut history = CommandHistory::default();
+            run(&mut history, 0, "make", 0);
+            history.apply(ShellMark::OutputStart {
+                line: 5,
+                command: "sleep 10".to_string(),
+            });
+
+            let list = history.list();
+            assert_eq!(list.len(), 2);
+            assert_eq!(list[1].command.as_deref(), Some("sleep 10"));
+            assert_eq!(list[1].prompt_line, 5);
+            assert_eq!(list[1].finished_at, None);
+        }
+
+        #[test]
+        fn oldest_commands_are_forgotten() {
+            let mut history = CommandHistory::default();
+            for i in 0..MAX_COMMANDS as u64 + 5 {
+                run(&mut history, i * 4, &i.to_string(), 0);
+            }
+            let list = history.list();
+            assert_eq!(list.len(), MAX_COMMANDS);
+            assert_eq!(list[0].command.as_deref(), Some("5"));
+        }
+    }
+}
@@ -0,0 +1,43 @@
+pub mod single_instance {
+    use std::{
+        fs::{File, OpenOptions, TryLockError},
+        path::PathBuf,
+    };
+
+    use snafu::{IntoError, ResultExt};
+
+    use crate::common::{
+        error::{TartError, WriteFileSnafu},
+        tartd,
+    };
+
+    pub fn lock_path() -> PathBuf {
+        tartd::socket_dir().join("tart.lock")
+    }
+
+    /// Held by the instance that later launches hand their arguments to, for as long as it runs.
+    /// The OS lets go of it when the process exits, however that happens.
+    #[derive(Debug)]
+    pub struct InstanceLock {
+        _file: File,
+    }
+
+    impl InstanceLock {
+        /// Takes the lock, or `None` if another instance already holds it.
+        pub fn acquire() -> Result<Option<InstanceLock>, TartError> {
+            let path = lock_path();
+            tartd::create_socket_dir(&path)?;
+            let file = OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(&path)
+                .context(WriteFileSnafu { path: &path })?;
+            match file.try_lock() {
+                Ok(()) => Ok(Some(InstanceLock { _file: file })),
+                Err(TryLockError::WouldBlock) => Ok(None),
+                Err(TryLockError::Error(e)) => Err(WriteFileSnafu { path }.into_error(e)),
+            }
+        }
+    }
+}
@@ -0,0 +1,102 @@
+pub mod shell_integration {
+    use std::{
+        collections::HashMap,
+        env, fs,
+        path::{Path, PathBuf},
+    };
+
+    use snafu::ResultExt;
+
+    use crate::common::{
+        error::{TartError, WriteFileSnafu},
+        shell::shell_name,
+    };
+
+    const BASH: &str = include_str!("../../shell-integration/tart.bash");
+    const BASHRC: &str = include_str!("../../shell-integration/bashrc");
+    const ZSH: &str = include_str!("../../shell-integration/tart.zsh");
+    const ZSHENV: &str = include_str!("../../shell-integration/zshenv");
+    const FISH: &str = include_str!("../../shell-integration/tart.fish");
+
+    /// What to launch a shell with so that it loads the integration scripts.
+    #[derive(Clone, Debug, Default)]
+    pub struct Injection {
+        pub args: Vec<String>,
+        pub env: Vec<(String, String)>,
+    }
+
+    /// Where the scripts are written out for shells to read them.
+    pub fn directory() -> PathBuf {
+        dirs::data_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("tart")
+            .join("shell-integration")
+    }
+
+    /// Prepares `command` to load the integration for its shell while still reading the
+    /// user's own startup files, or `None` if it isn't a shell we have scripts for. `env`
+    /// is the environment the terminal sets on top of ours.
+    pub fn inject(
+        command: &str,
+        login: bool,
+        env: &HashMap<String, String>,
+    ) -> Result<Option<Injection>, TartError> {
+        let inherited = |key: &str| env.get(key).cloned().or_else(|| env::var(key).ok());
+        let login_args = || if login { vec!["-l".to_string()] } else { Vec::new() };
+
+        let injection = match shell_name(command).as_str() {
+            "bash" => {
+                // A login shell ignores --rcfile, so the wrapper reads the profile itself
+                let dir = directory().join("bash");
+                write(&dir, "tart.bash", BASH)?;
+                let rcfile = write(&dir, "bashrc", BASHRC)?;
+                let mut injection = Injection {
+                    args: vec!["--rcfile".to_string(), rcfile.to_string_lossy().to_string()],
+                    env: Vec::new(),
+                };
+                if login {
+                    injection.env.push(("TART_BASH_LOGIN".to_string(), "1".to_string()));
+                }
+                injection
+            }
+            "zsh" => {
+                let dir = directory().join("zsh");
+                write(&dir, "tart.zsh", ZSH)?;
+                write(&dir, ".zshenv", ZSHENV)?;
+                Injection {
+                    args: login_args(),
+                    env: vec![
+                        ("TART_ZDOTDIR".to_string(), inherited("ZDOTDIR").unwrap_or_default()),
+                        ("ZDOTDIR".to_string(), dir.to_string_lossy().to_string()),
+                    ],
+                }
+            }
+            "fish" => {
+                let dir = directory().join("fish");
+                write(&dir.join("fish").join("vendor_conf.d"), "tart.fish", FISH)?;
+                let original = inherited("XDG_DATA_DIRS").filter(|dirs| !dirs.is_empty());
+                let data_dirs = format!(
+                    "{}:{}",
+                    dir.display(),
+                    original.as_deref().unwrap_or("/usr/local/share:/usr/share")
+                );
+                Injection {
+                    args: login_args(),
+                    env: vec![
+                        ("TART_XDG_DATA_DIRS".to_string(), original.unwrap_or_default()),
+                        ("XDG_DATA_DIRS".to_string(), data_dirs),
+                    ],
+                }
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(injection))
+    }
+
+    fn write(dir: &Path, name: &str, content: &str) -> Result<PathBuf, TartError> {
+        fs::create_dir_all(dir).context(WriteFileSnafu { path: dir })?;
+        let path = dir.join(name);
+        fs::write(&path, content).context(WriteFileSnafu { path: &path })?;
+        Ok(path)
+    }
+}
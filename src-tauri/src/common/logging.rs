@@ -0,0 +1,387 @@
+pub mod session_log {
+    use std::{
+        fmt,
+        fs::{self, File, OpenOptions},
+        io::{self, Write},
+        path::{Path, PathBuf},
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    use serde::{Deserialize, Serialize};
+    use snafu::ResultExt;
+    use uuid::Uuid;
+    use vte::{Parser, Perform};
+
+    use crate::common::error::{TartError, WriteFileSnafu};
+
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+    pub enum LogFormat {
+        /// Exactly what the program wrote, escape sequences and all
+        Raw,
+
+        /// Just the text, without escape sequences
+        Stripped,
+
+        /// The text with every line prefixed by the UTC time it started at
+        #[default]
+        Timestamped,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    pub struct LogTarget {
+        /// Supports the `{id}` and `{time}` placeholders
+        pub path: PathBuf,
+        pub format: LogFormat,
+
+        /// Size at which the log is rotated, 0 lets it grow forever
+        pub max_bytes: u64,
+
+        /// How many rotated logs (`<path>.1` being the newest) are kept around
+        pub keep: usize,
+    }
+
+    impl LogTarget {
+        fn render(&self, id: Uuid) -> LogTarget {
+            let path = self
+                .path
+                .to_string_lossy()
+                .replace("{id}", &id.to_string())
+                .replace("{time}", &file_timestamp(SystemTime::now()));
+            LogTarget {
+                path: PathBuf::from(path),
+                ..self.clone()
+            }
+        }
+    }
+
+    /// Collects the printable text out of terminal output.
+    #[derive(Default)]
+    struct Text {
+        text: String,
+
+        /// A carriage return that may still turn out to be part of a `\r\n`, possibly in
+        /// the next write
+        carriage_return: bool,
+    }
+
+    impl Text {
+        /// A line redrawn after a lone `\r`, like a progress bar, goes on a line of its own
+        fn end_redrawn_line(&mut self) {
+            if self.carriage_return {
+                self.text.push('\n');
+                self.carriage_return = false;
+            }
+        }
+    }
+
+    impl Perform for Text {
+        fn print(&mut self, c: char) {
+            self.end_redrawn_line();
+            self.text.push(c);
+        }
+
+        fn execute(&mut self, byte: u8) {
+            match byte {
+                b'\n' => {
+                    self.carriage_return = false;
+                    self.text.push('\n');
+                }
+                b'\r' => self.carriage_return = true,
+                b'\t' => {
+                    self.end_redrawn_line();
+                    self.text.push('\t');
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Appends one terminal's output to a file, rotating it once it gets too big.
+    pub struct SessionLog {
+        target: LogTarget,
+        file: File,
+        written: u64,
+        parser: Parser,
+        text: Text,
+        at_line_start: bool,
+    }
+
+    impl SessionLog {
+        /// Opens (or continues) the log at `target`, rendered for terminal `id`.
+        pub fn open(target: &LogTarget, id: Uuid) -> Result<Self, TartError> {
+            let target = target.render(id);
+            let file = open(&target.path).context(WriteFileSnafu { path: &target.path })?;
+            let written = file.metadata().map(|meta| meta.len()).unwrap_or(0);
+            Ok(SessionLog {
+                target,
+                file,
+                written,
+                parser: Parser::new(),
+                text: Text::default(),
+                at_line_start: true,
+            })
+        }
+
+        pub fn target(&self) -> &LogTarget {
+            &self.target
+        }
+
+        pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
+            let bytes = match self.target.format {
+                LogFormat::Raw => data.to_vec(),
+                LogFormat::Stripped => self.strip(data).into_bytes(),
+                LogFormat::Timestamped => {
+                    let text = self.strip(data);
+                    self.stamp(&text).into_bytes()
+                }
+            };
+            if bytes.is_empty() {
+                return Ok(());
+            }
+
+            let max = self.target.max_bytes;
+            if max > 0 && self.written > 0 && self.written + bytes.len() as u64 > max {
+                self.rotate()?;
+            }
+            self.file.write_all(&bytes)?;
+            self.written += bytes.len() as u64;
+            Ok(())
+        }
+
+        pub fn finish(mut self) -> io::Result<()> {
+            self.file.flush()
+        }
+
+        fn strip(&mut self, data: &[u8]) -> String {
+            for byte in data {
+                self.parser.advance(&mut self.text, *byte);
+            }
+            std::mem::take(&mut self.text.text)
+        }
+
+        fn stamp(&mut self, text: &str) -> String {
+            let mut stamped = String::with_capacity(text.len());
+            let now = timestamp(SystemTime::now());
+            for c in text.chars() {
+                if self.at_line_start {
+                    stamped.push_str(&format!("[{}] ", now));
+                    self.at_line_start = false;
+                }
+                stamped.push(c);
+                self.at_line_start = c == '\n';
+            }
+            stamped
+        }
+
+        /// Moves `<path>` to `<path>.1`, `<path>.1` to `<path>.2` and so on, dropping the
+        /// oldest, and starts over with an empty file.
+        fn rotate(&mut self) -> io::Result<()> {
+            self.file.flush()?;
+            let path = &self.target.path;
+            let rotated = |index: usize| PathBuf::from(format!("{}.{}", path.display(), index));
+            if self.target.keep == 0 {
+                fs::remove_file(path)?;
+            } else {
+                let _ = fs::remove_file(rotated(self.target.keep));
+                for index in (1..self.target.keep).rev() {
+                    if rotated(index).exists() {
+                        fs::rename(rotated(index), rotated(index + 1))?;
+                    }
+                }
+                fs::rename(path, rotated(1))?;
+            }
+            self.file = open(path)?;
+            self.written = 0;
+            Ok(())
+        }
+    }
+
+    impl fmt::Debug for SessionLog {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("SessionLog")
+                .field("target", &self.target)
+                .field("written", &self.written)
+                .finish_non_exhaustive()
+        }
+    }
+
+    fn open(path: &Path) -> io::Result<File> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        OpenOptions::new().create(true).append(true).open(path)
+    }
+
+    /// The UTC date and time of `time` as (year, month, day, hour, minute, second, millisecond).
+    fn utc(time: SystemTime) -> (i64, u64, u64, u64, u64, u64, u32) {
+        let elapsed = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let seconds = elapsed.as_secs();
+        let days = (seconds / 86_400) as i64;
+        let time_of_day = seconds % 86_400;
+
+        // Howard Hinnant's civil_from_days
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let day_of_era = (z - era * 146_097) as u64;
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+        let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+        let year = year_of_era as i64 + era * 400 + i64::from(month <= 2);
+
+        (
+            year,
+            month,
+            day,
+            time_of_day / 3600,
+            time_of_day % 3600 / 60,
+            time_of_day % 60,
+            elapsed.subsec_millis(),
+        )
+    }
+
+    /// RFC 3339 in UTC with milliseconds, e.g. `2024-08-01T12:34:56.789Z`.
+    fn timestamp(time: SystemTime) -> String {
+        let (year, month, day, hour, minute, second, millis) = utc(time);
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            year, month, day, hour, minute, second, millis
+        )
+    }
+
+    /// A timestamp that is safe in file names and sorts chronologically.
+    fn file_timestamp(time: SystemTime) -> String {
+        let (year, month, day, hour, minute, second, _) = utc(time);
+        format!(
+            "{:04}{:02}{:02}-{:02}{:02}{:02}",
+            year, month, day, hour, minute, second
+        )
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::{env, time::Duration};
+
+        use super::*;
+
+        fn at(seconds: u64, millis: u64) -> SystemTime {
+            UNIX_EPOCH + Duration::from_millis(seconds * 1000 + millis)
+        }
+
+        fn target(dir: &Path, format: LogFormat, max_bytes: u64, keep: usize) -> LogTarget {
+            LogTarget {
+                path: dir.join("{id}.log"),
+                format,
+                max_bytes,
+                keep,
+            }
+        }
+
+        fn scratch() -> PathBuf {
+            env::temp_dir().join(format!("tart-test-{}", Uuid::new_v4()))
+        }
+
+        #[test]
+        fn utc_dates() {
+            assert_eq!(utc(at(0, 0)), (1970, 1, 1, 0, 0, 0, 0));
+            assert_eq!(utc(at(86_399, 999)), (1970, 1, 1, 23, 59, 59, 999));
+            // Leap days, including 2000 which is one despite being a century
+            assert_eq!(utc(at(951_782_400, 0)), (2000, 2, 29, 0, 0, 0, 0));
+            assert_eq!(utc(at(951_868_800, 0)), (2000, 3, 1, 0, 0, 0, 0));
+            assert_eq!(utc(at(1_709_251_199, 0)), (2024, 2, 29, 23, 59, 59, 0));
+            assert_eq!(utc(at(1_735_689_599, 0)), (2024, 12, 31, 23, 59, 59, 0));
+            assert_eq!(utc(at(4_107_542_400, 0)), (2100, 3, 1, 0, 0, 0, 0));
+        }
+
+        #[test]
+        fn timestamps() {
+            assert_eq!(timestamp(at(1_722_515_696, 789)), "2024-08-01T12:34:56.789Z");
+            assert_eq!(file_timestamp(at(1_722_515_696, 789)), "20240801-123456");
+        }
+
+        #[test]
+        fn stripped_logs_keep_only_text() {
+            let dir = scratch();
+            let id = Uuid::new_v4();
+            let mut log = SessionLog::open(&target(&dir, LogFormat::Stripped, 0, 0), id).unwrap();
+            log.write(b"\x1b[1;31mred\x1b[0m\tplain\r\n\x1b]0;title\x07").unwrap();
+            // A sequence split across writes is still recognised
+            log.write(b"\x1b[3").unwrap();
+            log.write(b"2mgreen\r").unwrap();
+            log.write(b"\n10%\r20%\rdone\n").unwrap();
+            let path = log.target().path.clone();
+            log.finish().unwrap();
+
+            let text = fs::read_to_string(&path).unwrap();
+            assert_eq!(text, "red\tplain\ngreen\n10%\n20%\ndone\n");
+            let _ = fs::remove_dir_all(&dir);
+        }
+
+        #[test]
+        fn timestamped_logs_stamp_every_line() {
+            let dir = scratch();
+            let target = target(&dir, LogFormat::Timestamped, 0, 0);
+            let mut log = SessionLog::open(&target, Uuid::new_v4()).unwrap();
+            log.write(b"one\r\ntw").unwrap();
+            log.write(b"o\nthree").unwrap();
+            let path = log.target().path.clone();
+            log.finish().unwrap();
+
+            let text = fs::read_to_string(&path).unwrap();
+            let lines = text.lines().collect::<Vec<_>>();
+            assert_eq!(lines.len(), 3);
+            for (line, expected) in lines.iter().zip(["one", "two", "three"]) {
+                // `[2024-08-01T12:34:56.789Z] `
+                let (stamp, rest) = line.split_at(27);
+                assert!(stamp.starts_with('[') && stamp.ends_with("Z] "), "{:?}", line);
+                assert_eq!(&stamp[11..12], "T");
+                assert_eq!(rest, expected);
+            }
+            let _ = fs::remove_dir_all(&dir);
+        }
+
+        #[test]
+        fn logs_rotate_and_keep_the_newest() {
+            let dir = scratch();
+            let id = Uuid::new_v4();
+            let mut log = SessionLog::open(&target(&dir, LogFormat::Raw, 10, 2), id).unwrap();
+            for chunk in ["aaaaaa", "bbbbbb", "cccccc", "dddddd"] {
+                log.write(chunk.as_bytes()).unwrap();
+            }
+            let path = log.target().path.clone();
+            log.finish().unwrap();
+
+            let read = |suffix: &str| {
+                fs::read_to_string(format!("{}{}", path.display(), suffix)).ok()
+            };
+            assert_eq!(read("").as_deref(), Some("dddddd"));
+            assert_eq!(read(".1").as_deref(), Some("cccccc"));
+            assert_eq!(read(".2").as_deref(), Some("bbbbbb"));
+            assert_eq!(read(".3"), None);
+
+            // Reopening continues the current file and counts what's already in it
+            let mut log = SessionLog::open(&target(&dir, LogFormat::Raw, 10, 2), id).unwrap();
+            log.write(b"eeee").unwrap();
+            log.write(b"f").unwrap();
+            log.finish().unwrap();
+            assert_eq!(read("").as_deref(), Some("f"));
+            assert_eq!(read(".1").as_deref(), Some("ddddddeeee"));
+            let _ = fs::remove_dir_all(&dir);
+        }
+
+        #[test]
+        fn nothing_is_kept_without_rotated_logs() {
+            let dir = scratch();
+            let target = target(&dir, LogFormat::Raw, 4, 0);
+            let mut log = SessionLog::open(&target, Uuid::new_v4()).unwrap();
+            log.write(b"abc").unwrap();
+            log.write(b"def").unwrap();
+            let path = log.target().path.clone();
+            log.finish().unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), "def");
+            assert!(!PathBuf::from(format!("{}.1", path.display())).exists());
+            let _ = fs::remove_dir_all(&dir);
+        }
+    }
+}
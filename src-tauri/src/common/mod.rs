@@ -2,4 +2,55 @@ mod terminal;
 pub use terminal::term;
 
 mod state;
-pub use state::app_state;
\ No newline at end of file
+pub use state::app_state;
+
+mod stream;
+pub use stream::output;
+
+mod system;
+pub use system::shell;
+
+mod profiles;
+pub use profiles::profile;
+
+mod settings;
+pub use settings::config;
+
+mod errors;
+pub use errors::error;
+
+mod emulator;
+pub use emulator::screen;
+
+mod history;
+pub use history::command_history;
+
+mod integration;
+pub use integration::shell_integration;
+
+mod attention;
+pub use attention::notifications;
+
+mod recording;
+pub use recording::asciicast;
+
+mod logging;
+pub use logging::session_log;
+
+mod bus;
+pub use bus::event_bus;
+
+mod daemon;
+pub use daemon::tartd;
+
+mod session;
+pub use session::saved_session;
+
+mod remote;
+pub use remote::remote_control;
+
+mod cli;
+pub use cli::command_line;
+
+mod instance;
+pub use instance::single_instance;
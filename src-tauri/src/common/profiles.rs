@@ -0,0 +1,348 @@
+pub mod profile {
+    use std::{
+        collections::HashMap,
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use serde::{Deserialize, Serialize};
+    use snafu::{OptionExt, ResultExt};
+
+    use crate::common::{
+        error::{
+            ParseTomlSnafu, ProfileExistsSnafu, ProfilesUnreadableSnafu, ReadFileSnafu,
+            SerializeTomlSnafu, TartError, UnknownProfileSnafu, WriteFileSnafu,
+        },
+        notifications::NotificationPolicy,
+        shell::{default_shell, shell_name},
+        term::{CloseOnExit, TerminalOptions},
+    };
+
+    /// A named, reusable set of options for launching a terminal.
+    #[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+    pub struct Profile {
+        pub name: String,
+        pub command: Option<String>,
+        pub args: Option<Vec<String>>,
+        pub login: Option<bool>,
+        pub cwd: Option<String>,
+        pub env: Option<HashMap<String, String>>,
+        pub env_remove: Option<Vec<String>>,
+        pub clear_env: Option<bool>,
+
+        /// Supports the `{profile}`, `{command}` and `{cwd}` placeholders
+        pub title: Option<String>,
+        pub close_on_exit: Option<CloseOnExit>,
+        pub shell_integration: Option<bool>,
+        pub notifications: Option<NotificationPolicy>,
+    }
+
+    impl Profile {
+        /// Builds the options for a terminal launched from this profile, with `overrides`
+        /// taking precedence over anything the profile sets.
+        pub fn options(&self, overrides: Option<TerminalOptions>) -> TerminalOptions {
+            let options = TerminalOptions {
+                command: self.command.clone(),
+                args: self.args.clone(),
+                login: self.login,
+                title: self.title.clone(),
+                cwd: self.cwd.clone(),
+                env: self.env.clone(),
+                env_remove: self.env_remove.clone(),
+                clear_env: self.clear_env,
+                close_on_exit: self.close_on_exit,
+                shell_integration: self.shell_integration,
+                notifications: self.notifications,
+                profile: Some(self.name.clone()),
+                ..TerminalOptions::default()
+            };
+
+            let mut options = match overrides {
+                Some(overrides) => options.merge(overrides),
+                None => options,
+            };
+            let command = options.command.get_or_insert_with(default_shell).clone();
+            options.title = options
+                .title
+                .map(|template| self.render_title(&template, &command, options.cwd.as_deref()));
+            options
+        }
+
+        fn render_title(&self, template: &str, command: &str, cwd: Option<&str>) -> String {
+            template
+                .replace("{profile}", &self.name)
+                .replace("{command}", &shell_name(command))
+                .replace("{cwd}", cwd.unwrap_or("~"))
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, Default)]
+    struct ProfileFile {
+        #[serde(default, rename = "profile")]
+        profiles: Vec<Profile>,
+    }
+
+    /// Profiles loaded from (and persisted back to) `profiles.toml` in the config directory.
+    #[derive(Clone, Debug)]
+    pub struct ProfileStore {
+        path: PathBuf,
+        profiles: Vec<Profile>,
+
+        /// Why the file couldn't be loaded, in which case it mustn't be saved over
+        error: Option<String>,
+    }
+
+    impl ProfileStore {
+        pub fn default_path() -> PathBuf {
+            dirs::config_dir()
+                .unwrap_or_else(|| PathBuf::from("."))
+                .join("tart")
+                .join("profiles.toml")
+        }
+
+        /// Loads the profiles at `path`, seeding the file with a login shell profile if it doesn't exist yet.
+        pub fn load(path: &Path) -> Result<Self, TartError> {
+            if !path.exists() {
+                let store = ProfileStore {
+                    path: path.to_path_buf(),
+                    profiles: vec![Profile {
+                        name: "Shell".to_string(),
+                        login: Some(true),
+                        title: Some("{command}".to_string()),
+                        ..Profile::default()
+                    }],
+                    error: None,
+                };
+                store.save()?;
+                return Ok(store);
+            }
+
+            let content = fs::read_to_string(path).context(ReadFileSnafu { path })?;
+            let file = toml::from_str::<ProfileFile>(&content).context(ParseTomlSnafu { path })?;
+            Ok(ProfileStore {
+                path: path.to_path_buf(),
+                profiles: file.profiles,
+                error: None,
+            })
+        }
+
+        /// An empty store that is only written once a profile is saved.
+        #[cfg(test)]
+        pub fn empty(path: &Path) -> Self {
+            ProfileStore {
+                path: path.to_path_buf(),
+                profiles: Vec::new(),
+                error: None,
+            }
+        }
+
+        /// A store standing in for a file that failed to load. It has no profiles and refuses
+        /// changes until the file loads again, so the user's profiles are never overwritten.
+        pub fn unreadable(path: &Path, error: &TartError) -> Self {
+            ProfileStore {
+                path: path.to_path_buf(),
+                profiles: Vec::new(),
+                error: Some(error.to_string()),
+            }
+        }
+
+        /// Retries loading the file if it failed before, failing while it still doesn't load.
+        pub fn check(&mut self) -> Result<(), TartError> {
+            if self.error.is_none() {
+                return Ok(());
+            }
+            match ProfileStore::load(&self.path) {
+                Ok(store) => {
+                    *self = store;
+                    Ok(())
+                }
+                Err(e) => {
+                    self.error = Some(e.to_string());
+                    ProfilesUnreadableSnafu {
+                        path: &self.path,
+                        reason: e.to_string(),
+                    }
+                    .fail()
+                }
+            }
+        }
+
+        pub fn list(&self) -> Vec<Profile> {
+            self.profiles.clone()
+        }
+
+        pub fn get(&self, name: &str) -> Option<Profile> {
+            self.profiles.iter().find(|p| p.name == name).cloned()
+        }
+
+        pub fn create(&mut self, profile: Profile) -> Result<Profile, TartError> {
+            self.check()?;
+            if self.get(&profile.name).is_some() {
+                return ProfileExistsSnafu { name: profile.name }.fail();
+            }
+            self.profiles.push(profile.clone());
+            self.save()?;
+            Ok(profile)
+        }
+
+        /// Replaces the profile called `name`, which may also rename it.
+        pub fn update(&mut self, name: &str, profile: Profile) -> Result<Profile, TartError> {
+            self.check()?;
+            if profile.name != name && self.get(&profile.name).is_some() {
+                return ProfileExistsSnafu { name: profile.name }.fail();
+            }
+            let existing = self
+                .profiles
+                .iter_mut()
+                .find(|p| p.name == name)
+                .context(UnknownProfileSnafu { name })?;
+            *existing = profile.clone();
+            self.save()?;
+            Ok(profile)
+        }
+
+        pub fn delete(&mut self, name: &str) -> Result<Profile, TartError> {
+            self.check()?;
+            let index = self
+                .profiles
+                .iter()
+                .position(|p| p.name == name)
+                .context(UnknownProfileSnafu { name })?;
+            let removed = self.profiles.remove(index);
+            self.save()?;
+            Ok(removed)
+        }
+
+        fn save(&self) -> Result<(), TartError> {
+            let content = toml::to_string_pretty(&ProfileFile {
+                profiles: self.profiles.clone(),
+            })
+            .context(SerializeTomlSnafu)?;
+            if let Some(parent) = self.path.parent() {
+                fs::create_dir_all(parent).context(WriteFileSnafu { path: parent })?;
+            }
+            fs::write(&self.path, content).context(WriteFileSnafu { path: &self.path })
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::env;
+
+        use uuid::Uuid;
+
+        use super::*;
+
+        fn scratch() -> PathBuf {
+            let dir = env::temp_dir().join(format!("tart-test-{}", Uuid::new_v4()));
+            fs::create_dir_all(&dir).unwrap();
+            dir.join("profiles.toml")
+        }
+
+        fn profile(name: &str) -> Profile {
+            Profile {
+                name: name.to_string(),
+                ..Default::default()
+            }
+        }
+
+        #[test]
+        fn overrides_take_precedence() {
+            let profile = Profile {
+                command: Some("/bin/zsh".to_string()),
+                args: Some(vec!["-f".to_string()]),
+                cwd: Some("/srv".to_string()),
+                env: Some(HashMap::from([
+                    ("EDITOR".to_string(), "vi".to_string()),
+                    ("PAGER".to_string(), "less".to_string()),
+                ])),
+                title: Some("{profile}: {command} in {cwd}".to_string()),
+                ..profile("work")
+            };
+
+            let options = profile.options(None);
+            assert_eq!(options.profile.as_deref(), Some("work"));
+            assert_eq!(options.title.as_deref(), Some("work: zsh in /srv"));
+
+            let options = profile.options(Some(TerminalOptions {
+                cwd: Some("/tmp".to_string()),
+                env: Some(HashMap::from([("EDITOR".to_string(), "nano".to_string())])),
+                ..Default::default()
+            }));
+            assert_eq!(options.command.as_deref(), Some("/bin/zsh"));
+            assert_eq!(options.args, Some(vec!["-f".to_string()]));
+            assert_eq!(options.title.as_deref(), Some("work: zsh in /tmp"));
+            let env = options.env.unwrap();
+            assert_eq!((env["EDITOR"].as_str(), env["PAGER"].as_str()), ("nano", "less"));
+
+            // Another command doesn't get the profile's arguments
+            let options = profile.options(Some(TerminalOptions {
+                command: Some("/usr/bin/fish".to_string()),
+                ..Default::default()
+            }));
+            assert_eq!(options.args, None);
+            assert_eq!(options.title.as_deref(), Some("work: fish in /srv"));
+        }
+
+        #[test]
+        fn title_placeholders() {
+            let profile = profile("dev");
+            assert_eq!(profile.render_title("{profile} {command}", "/bin/bash", None), "dev bash");
+            assert_eq!(profile.render_title("{cwd}", "bash", None), "~");
+            assert_eq!(profile.render_title("{cwd} {cwd}", "bash", Some("/a")), "/a /a");
+            assert_eq!(profile.render_title("plain", "bash", None), "plain");
+        }
+
+        #[test]
+        fn create_update_delete() {
+            let path = scratch();
+            let mut store = ProfileStore::load(&path).unwrap();
+            assert_eq!(store.list(), vec![Profile {
+                name: "Shell".to_string(),
+                login: Some(true),
+                title: Some("{command}".to_string()),
+                ..Default::default()
+            }]);
+
+            store.create(profile("a")).unwrap();
+            assert_eq!(store.create(profile("a")).unwrap_err().kind(), "profile_exists");
+
+            // Renaming onto another profile's name is refused too
+            assert_eq!(store.update("a", profile("Shell")).unwrap_err().kind(), "profile_exists");
+            assert_eq!(store.update("nope", profile("b")).unwrap_err().kind(), "unknown_profile");
+            let updated = Profile {
+                command: Some("htop".to_string()),
+                ..profile("b")
+            };
+            store.update("a", updated.clone()).unwrap();
+            assert_eq!(store.get("a"), None);
+            assert_eq!(store.get("b"), Some(updated.clone()));
+
+            assert_eq!(store.delete("a").unwrap_err().kind(), "unknown_profile");
+            assert_eq!(store.delete("Shell").unwrap().name, "Shell");
+
+            // Every change went to the file
+            assert_eq!(ProfileStore::load(&path).unwrap().list(), vec![updated]);
+        }
+
+        #[test]
+        fn unreadable_file_is_never_saved_over() {
+            let path = scratch();
+            fs::write(&path, "[[profile]\nname = ").unwrap();
+            let error = ProfileStore::load(&path).unwrap_err();
+            let mut store = ProfileStore::unreadable(&path, &error);
+
+            let refused = store.create(profile("zsh")).unwrap_err();
+            assert_eq!(refused.kind(), "profiles_unreadable");
+            assert!(store.list().is_empty());
+            assert_eq!(fs::read_to_string(&path).unwrap(), "[[profile]\nname = ");
+
+            // Once the file is fixed it's picked up again before anything is changed
+            fs::write(&path, "[[profile]]\nname = \"bash\"\n").unwrap();
+            store.create(profile("zsh")).unwrap();
+            let names = store.list().into_iter().map(|p| p.name).collect::<Vec<_>>();
+            assert_eq!(names, ["bash", "zsh"]);
+            assert_eq!(ProfileStore::load(&path).unwrap().list().len(), 2);
+        }
+    }
+}
@@ -0,0 +1,376 @@
+pub mod asciicast {
+    use std::{
+        fs::{self, File},
+        io::{self, LineWriter, Write},
+        path::{Path, PathBuf},
+        time::{Instant, SystemTime, UNIX_EPOCH},
+    };
+
+    use portable_pty::PtySize;
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+    use snafu::ResultExt;
+
+    use crate::common::{
+        error::{ParseCastSnafu, ReadFileSnafu, TartError, WriteFileSnafu},
+        output::Utf8Decoder,
+    };
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    pub struct RecordingInfo {
+        pub path: PathBuf,
+        pub include_input: bool,
+
+        /// Seconds since the Unix epoch, as in the file's header
+        pub started_at: u64,
+    }
+
+    /// Writes a terminal's session to an asciicast v2 file, one event per line as it happens.
+    #[derive(Debug)]
+    pub struct Recorder {
+        writer: LineWriter<File>,
+        started: Instant,
+        info: RecordingInfo,
+
+        /// Holds the start of a UTF-8 sequence split across reads until the rest arrives
+        decoder: Utf8Decoder,
+    }
+
+    impl Recorder {
+        pub fn create(
+            path: &Path,
+            size: PtySize,
+            include_input: bool,
+            title: Option<String>,
+            env: &[(&str, &str)],
+        ) -> Result<Self, TartError> {
+            let file = File::create(path).context(WriteFileSnafu { path })?;
+            let started_at = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default();
+
+            let mut header = json!({
+                "version": 2,
+                "width": size.cols,
+                "height": size.rows,
+                "timestamp": started_at,
+                "env": env
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), json!(value)))
+                    .collect::<serde_json::Map<_, _>>(),
+            });
+            if let Some(title) = title {
+                header["title"] = json!(title);
+            }
+
+            let mut recorder = Recorder {
+                writer: LineWriter::new(file),
+                started: Instant::now(),
+                info: RecordingInfo {
+                    path: path.to_path_buf(),
+                    include_input,
+                    started_at,
+                },
+                decoder: Utf8Decoder::new(),
+            };
+            writeln!(recorder.writer, "{}", header).context(WriteFileSnafu { path })?;
+            Ok(recorder)
+        }
+
+        pub fn info(&self) -> &RecordingInfo {
+            &self.info
+        }
+
+        pub fn output(&mut self, data: &[u8]) -> io::Result<()> {
+            let text = self.decoder.decode(data);
+            self.event("o", &text)
+        }
+
+        /// Records what was typed, if the recording was started with input included.
+        pub fn input(&mut self, data: &str) -> io::Result<()> {
+            if !self.info.include_input {
+                return Ok(());
+            }
+            self.event("i", data)
+        }
+
+        pub fn resize(&mut self, size: PtySize) -> io::Result<()> {
+            self.event("r", &format!("{}x{}", size.cols, size.rows))
+        }
+
+        /// Writes out anything still held back and closes the file.
+        pub fn finish(mut self) -> io::Result<RecordingInfo> {
+            let text = self.decoder.finish();
+            self.event("o", &text)?;
+            self.writer.flush()?;
+            Ok(self.info)
+        }
+
+        fn event(&mut self, kind: &str, data: &str) -> io::Result<()> {
+            if data.is_empty() {
+                return Ok(());
+            }
+            // asciinema itself keeps microsecond precision
+            let elapsed = self.started.elapsed().as_micros() as f64 / 1_000_000.0;
+            let line = serde_json::to_string(&(elapsed, kind, data))?;
+            writeln!(self.writer, "{}", line)
+        }
+    }
+
+    /// Where a playback terminal is in its recording, times in seconds of the recording.
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+    pub struct PlaybackStatus {
+        pub position: f64,
+        pub duration: f64,
+        pub speed: f64,
+        pub paused: bool,
+    }
+
+    /// The events of a recording that matter for playing it back.
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum CastEvent {
+        Output(String),
+        Resize(PtySize),
+    }
+
+    #[derive(Deserialize)]
+    struct CastHeader {
+        version: u8,
+        width: u16,
+        height: u16,
+        title: Option<String>,
+        idle_time_limit: Option<f64>,
+
+        /// Only in v1, where events are part of the header and timed relative to each other
+        stdout: Option<Vec<(f64, String)>>,
+    }
+
+    /// A recording loaded for playback, with event times in seconds from the start.
+    #[derive(Clone, Debug)]
+    pub struct Cast {
+        pub size: PtySize,
+        pub title: Option<String>,
+        pub idle_time_limit: Option<f64>,
+        pub events: Vec<(f64, CastEvent)>,
+    }
+
+    impl Cast {
+        /// Loads an asciicast v1 or v2 file.
+        pub fn load(path: &Path) -> Result<Self, TartError> {
+            let content = fs::read_to_string(path).context(ReadFileSnafu { path })?;
+            let invalid = |reason: String| ParseCastSnafu { path, reason }.build();
+
+            // v2 is a header line followed by one event per line, v1 is a single document
+            let mut lines = content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+            let (_, first) = lines.next().ok_or_else(|| invalid("the file is empty".to_string()))?;
+            let header = match serde_json::from_str::<CastHeader>(first) {
+                Ok(header) if header.version == 2 => header,
+                _ => {
+                    let header = serde_json::from_str::<CastHeader>(&content)
+                        .map_err(|e| invalid(e.to_string()))?;
+                    if header.version != 1 {
+                        return Err(invalid(format!("unsupported version {}", header.version)));
+                    }
+                    let mut time = 0.0;
+                    let mut events = Vec::new();
+                    for (index, (delay, data)) in header.stdout.iter().flatten().enumerate() {
+                        if !is_seconds(*delay) {
+                            return Err(invalid(format!("event {}: bad delay {}", index + 1, delay)));
+                        }
+                        time += delay;
+                        events.push((time, CastEvent::Output(data.clone())));
+                    }
+                    return Cast::new(header, events).map_err(invalid);
+                }
+            };
+
+            let mut events = Vec::new();
+            let mut previous = 0.0;
+            for (index, line) in lines {
+                let (time, code, data) = serde_json::from_str::<(f64, String, String)>(line)
+                    .map_err(|e| invalid(format!("line {}: {}", index + 1, e)))?;
+                if !is_seconds(time) {
+                    return Err(invalid(format!("line {}: bad time {}", index + 1, time)));
+                }
+                if time < previous {
+                    return Err(invalid(format!(
+                        "line {}: time {} comes before the previous event's {}",
+                        index + 1,
+                        time,
+                        previous
+                    )));
+                }
+                previous = time;
+                let event = match code.as_str() {
+                    "o" => CastEvent::Output(data),
+                    "r" => match parse_size(&data) {
+                        Some(size) => CastEvent::Resize(size),
+                        None => return Err(invalid(format!("line {}: bad size {:?}", index + 1, data))),
+                    },
+                    // Input and markers don't change what's on screen
+                    _ => continue,
+                };
+                events.push((time, event));
+            }
+            Cast::new(header, events).map_err(invalid)
+        }
+
+        fn new(header: CastHeader, events: Vec<(f64, CastEvent)>) -> Result<Self, String> {
+            if let Some(limit) = header.idle_time_limit.filter(|limit| !is_seconds(*limit)) {
+                return Err(format!("bad idle_time_limit {}", limit));
+            }
+            Ok(Cast {
+                size: PtySize {
+                    rows: header.height,
+                    cols: header.width,
+                    pixel_width: 0,
+                    pixel_height: 0,
+                },
+                title: header.title,
+                idle_time_limit: header.idle_time_limit,
+                events,
+            })
+        }
+
+        /// Shortens every pause between events to at most `limit` seconds.
+        pub fn limit_idle_time(&mut self, limit: f64) {
+            let mut previous = 0.0;
+            let mut shift = 0.0;
+            for (time, _) in self.events.iter_mut() {
+                let gap = *time - previous;
+                previous = *time;
+                if gap > limit {
+                    shift += gap - limit;
+                }
+                *time -= shift;
+            }
+        }
+
+        /// Seconds until the last event.
+        pub fn duration(&self) -> f64 {
+            self.events.last().map(|(time, _)| *time).unwrap_or(0.0)
+        }
+    }
+
+    /// Whether `time` works as a time or a pause in a recording, which can't run backwards.
+    fn is_seconds(time: f64) -> bool {
+        time >= 0.0 && time.is_finite()
+    }
+
+    /// Parses the `COLSxROWS` of a resize event.
+    fn parse_size(size: &str) -> Option<PtySize> {
+        let (cols, rows) = size.split_once('x')?;
+        Some(PtySize {
+            rows: rows.parse().ok()?,
+            cols: cols.parse().ok()?,
+            pixel_width: 0,
+            pixel_height: 0,
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::env;
+
+        use uuid::Uuid;
+
+        use super::*;
+
+        fn load(content: &str) -> Result<Cast, TartError> {
+            let path = env::temp_dir().join(format!("tart-test-{}.cast", Uuid::new_v4()));
+            fs::write(&path, content).unwrap();
+            let cast = Cast::load(&path);
+            let _ = fs::remove_file(&path);
+            cast
+        }
+
+        fn output(time: f64, data: &str) -> (f64, CastEvent) {
+            (time, CastEvent::Output(data.to_string()))
+        }
+
+        #[test]
+        fn v2_header_and_events() {
+            let cast = load(concat!(
+                r#"{"version": 2, "width": 100, "height": 30, "title": "demo", "idle_time_limit": 2.5}"#,
+                "\n",
+                r#"[0.5, "o", "hello"]"#,
+                "\n\n",
+                r#"[0.75, "i", "typed"]"#,
+                "\n",
+                r#"[1.0, "r", "120x40"]"#,
+                "\n",
+                r#"[1.5, "m", "marker"]"#,
+                "\n",
+                r#"[2.0, "o", "world"]"#,
+                "\n",
+            ))
+            .unwrap();
+            assert_eq!((cast.size.cols, cast.size.rows), (100, 30));
+            assert_eq!(cast.title.as_deref(), Some("demo"));
+            assert_eq!(cast.idle_time_limit, Some(2.5));
+            let resize = PtySize {
+                rows: 40,
+                cols: 120,
+                pixel_width: 0,
+                pixel_height: 0,
+            };
+            assert_eq!(
+                cast.events,
+                vec![output(0.5, "hello"), (1.0, CastEvent::Resize(resize)), output(2.0, "world")]
+            );
+            assert_eq!(cast.duration(), 2.0);
+        }
+
+        #[test]
+        fn v1_delays_add_up() {
+            let cast = load(
+                r#"{"version": 1, "width": 80, "height": 24, "stdout": [[0.5, "a"], [0.25, "b"], [0, "c"]]}"#,
+            )
+            .unwrap();
+            assert_eq!((cast.size.cols, cast.size.rows), (80, 24));
+            assert_eq!(cast.events, vec![output(0.5, "a"), output(0.75, "b"), output(0.75, "c")]);
+        }
+
+        #[test]
+        fn bad_files_are_rejected() {
+            let header = r#"{"version": 2, "width": 80, "height": 24}"#;
+            let rejected = [
+                String::new(),
+                r#"{"version": 3, "width": 80, "height": 24}"#.to_string(),
+                format!("{}\nnot json", header),
+                format!("{}\n[-1, \"o\", \"x\"]", header),
+                format!("{}\n[1e999, \"o\", \"x\"]", header),
+                format!("{}\n[1.0, \"r\", \"wide\"]", header),
+                format!("{}\n[2.0, \"o\", \"x\"]\n[1.0, \"o\", \"y\"]", header),
+                // Events that are skipped still have to be in order
+                format!("{}\n[2.0, \"i\", \"x\"]\n[1.0, \"o\", \"y\"]", header),
+                r#"{"version": 1, "width": 80, "height": 24, "stdout": [[-0.5, "a"]]}"#.to_string(),
+                r#"{"version": 2, "width": 80, "height": 24, "idle_time_limit": -1}"#.to_string(),
+            ];
+            for content in rejected {
+                let error = load(&content).unwrap_err();
+                assert_eq!(error.kind(), "parse_cast", "{:?}", content);
+            }
+        }
+
+        #[test]
+        fn idle_time_is_compressed() {
+            let mut cast = load(concat!(
+                r#"{"version": 2, "width": 80, "height": 24}"#,
+                "\n",
+                r#"[5.0, "o", "a"]"#,
+                "\n",
+                r#"[6.0, "o", "b"]"#,
+                "\n",
+                r#"[16.0, "o", "c"]"#,
+                "\n",
+                r#"[16.5, "o", "d"]"#,
+            ))
+            .unwrap();
+            cast.limit_idle_time(2.0);
+            let times = cast.events.iter().map(|(time, _)| *time).collect::<Vec<_>>();
+            assert_eq!(times, [2.0, 3.0, 5.0, 5.5]);
+        }
+    }
+}
@@ -0,0 +1,165 @@
+pub mod remote_control {
+    // Only unix has a socket to listen on, elsewhere the CLI can't reach the GUI
+    #![cfg_attr(not(unix), allow(dead_code))]
+
+    use std::{
+        fs, io,
+        path::{Path, PathBuf},
+    };
+
+    #[cfg(unix)]
+    use std::{
+        io::{BufRead, BufReader},
+        thread,
+    };
+
+    use crossbeam_channel::Sender;
+    use snafu::IntoError;
+    use tauri::{AppHandle, Emitter, Manager};
+    use uuid::Uuid;
+
+    #[cfg(unix)]
+    use snafu::ResultExt;
+
+    #[cfg(unix)]
+    use crate::common::tartd::Clients;
+    use crate::common::{
+        app_state::{AppCommand, CommandResult},
+        error::{NotRunningSnafu, SocketSnafu, TartError},
+        tartd::{self, Backend, DaemonMessage, DaemonRequest},
+    };
+
+    /// Where a running GUI takes commands from the `tart` CLI, next to `tartd`'s socket.
+    pub fn socket_path() -> PathBuf {
+        tartd::socket_dir().join("tart.sock")
+    }
+
+    /// The GUI's end of the CLI socket, removed again when the app exits.
+    pub struct ControlSocket {
+        path: PathBuf,
+    }
+
+    impl ControlSocket {
+        /// Serves the CLI from the app's backend on a background thread.
+        #[cfg(unix)]
+        pub fn listen(app: &AppHandle) -> Result<ControlSocket, TartError> {
+            let path = socket_path();
+            let listener = tartd::bind(&path)?;
+            let app = app.clone();
+            thread::spawn(move || {
+                let clients = Clients::default();
+                for stream in listener.incoming() {
+                    let (reader, writer) = match stream.and_then(|stream| Ok((stream.try_clone()?, stream))) {
+                        Ok(halves) => halves,
+                        Err(e) => {
+                            println!("Failed to accept a connection: {}", e);
+                            continue;
+                        }
+                    };
+                    let (app, clients) = (app.clone(), clients.clone());
+                    thread::spawn(move || {
+                        tartd::serve_connection(&clients, reader, writer, |outgoing, request| {
+                            handle_request(&app, outgoing, request)
+                        })
+                    });
+                }
+            });
+            Ok(ControlSocket { path })
+        }
+
+        #[cfg(not(unix))]
+        pub fn listen(_app: &AppHandle) -> Result<ControlSocket, TartError> {
+            let path = socket_path();
+            Err(SocketSnafu { path }.into_error(io::ErrorKind::Unsupported.into()))
+        }
+
+        pub fn close(&self) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+
+    fn handle_request(app: &AppHandle, outgoing: &Sender<DaemonMessage>, request: DaemonRequest) -> DaemonMessage {
+        let backend = app.state::<Backend>();
+        match request {
+            DaemonRequest::Command { request, command } => {
+                let result = backend
+                    .run_command(app, (*command).clone())
+                    .unwrap_or_else(|e| command.wrap().result(Err::<(), _>(e)));
+                if let Some(id) = shown_terminal(&command, &result) {
+                    show_terminal(app, id);
+                }
+                DaemonMessage::CommandResult { request, result }
+            }
+            DaemonRequest::Subscribe { request, id } => DaemonMessage::Subscribed {
+                request,
+                result: backend.subscribe(id, tartd::stream(outgoing, request)),
+            },
+        }
+    }
+
+    /// The terminal the user should see after a command from the CLI: the one it focused, or
+    /// the one it opened.
+    fn shown_terminal(command: &AppCommand, result: &CommandResult) -> Option<Uuid> {
+        let Ok(value) = result.result() else {
+            return None;
+        };
+        match command {
+            AppCommand::SetFocus { id } => *id,
+            AppCommand::CreateTerminal { .. }
+            | AppCommand::CreateTerminalFromProfile { .. }
+            | AppCommand::CreatePlayback { .. } => {
+                value.get("id").and_then(|id| serde_json::from_value(id.clone()).ok())
+            }
+            _ => None,
+        }
+    }
+
+    /// Raises the main window and has it select `id`.
+    fn show_terminal(app: &AppHandle, id: Uuid) {
+        if let Some(window) = app.get_webview_window("main") {
+            let _ = window.unminimize();
+            let _ = window.show();
+            let _ = window.set_focus();
+        }
+        let _ = app.emit("tart://show", id);
+    }
+
+    /// Runs `command` in the running GUI, or in `tartd` when no GUI is open.
+    pub fn send(command: &AppCommand) -> Result<CommandResult, TartError> {
+        for path in [socket_path(), tartd::socket_path()] {
+            if let Some(result) = send_to(&path, command)? {
+                return Ok(result);
+            }
+        }
+        NotRunningSnafu.fail()
+    }
+
+    #[cfg(unix)]
+    fn send_to(path: &Path, command: &AppCommand) -> Result<Option<CommandResult>, TartError> {
+        let Some(mut stream) = tartd::connect(path)? else {
+            return Ok(None);
+        };
+        let request = Uuid::new_v4();
+        let message = DaemonRequest::Command {
+            request,
+            command: Box::new(command.clone()),
+        };
+        tartd::write_message(&mut stream, &message).context(SocketSnafu { path })?;
+
+        // tartd sends its events to everyone connected, so skip ahead to the reply
+        for line in BufReader::new(stream).lines() {
+            let line = line.context(SocketSnafu { path })?;
+            if let Ok(DaemonMessage::CommandResult { request: replied, result }) = serde_json::from_str(&line) {
+                if replied == request {
+                    return Ok(Some(result));
+                }
+            }
+        }
+        Err(SocketSnafu { path }.into_error(io::ErrorKind::UnexpectedEof.into()))
+    }
+
+    #[cfg(not(unix))]
+    fn send_to(path: &Path, _command: &AppCommand) -> Result<Option<CommandResult>, TartError> {
+        Err(SocketSnafu { path }.into_error(io::ErrorKind::Unsupported.into()))
+    }
+}
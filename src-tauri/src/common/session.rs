@@ -0,0 +1,245 @@
+pub mod saved_session {
+    #[cfg(unix)]
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+    use std::{
+        fs::{self, OpenOptions},
+        io::{self, Write},
+        path::{Path, PathBuf},
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    use serde::{Deserialize, Serialize};
+    use snafu::{IntoError, ResultExt};
+
+    use crate::common::{
+        error::{ErrorPayload, ParseSessionSnafu, ReadFileSnafu, SerializationSnafu, TartError, WriteFileSnafu},
+        term::{TerminalInfo, TerminalKind, TerminalManager, TerminalOptions, TerminalState},
+    };
+
+    /// Saving the open terminals on exit, so the next launch can offer to bring them back.
+    /// The saved file holds each terminal's environment, so on unix only its owner can read it.
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    #[serde(default)]
+    pub struct SessionConfig {
+        pub save: bool,
+
+        /// Lines of output to keep per terminal, 0 keeps none
+        pub scrollback_lines: usize,
+    }
+
+    impl Default for SessionConfig {
+        fn default() -> Self {
+            SessionConfig {
+                save: true,
+                scrollback_lines: 0,
+            }
+        }
+    }
+
+    /// A terminal as it was when tart exited, with enough to start it over.
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct SavedTerminal {
+        pub info: TerminalInfo,
+
+        /// The end of its output as text and SGR sequences, if the config asks for it
+        pub contents: Option<String>,
+    }
+
+    impl SavedTerminal {
+        /// The options to start it over with. A directory that's gone since falls back to its
+        /// closest parent that's still around, and to the default one after that.
+        pub fn options(&self) -> TerminalOptions {
+            let mut options = self.info.options();
+            options.cwd = options.cwd.as_deref().and_then(existing_dir);
+            options
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, Default)]
+    pub struct Session {
+        /// Milliseconds since the epoch
+        pub saved_at: u64,
+
+        /// In tab order
+        pub terminals: Vec<SavedTerminal>,
+    }
+
+    /// What `RestoreSession` brought back, and why the rest didn't make it.
+    #[derive(Serialize, Deserialize, Clone, Debug, Default)]
+    pub struct RestoredSession {
+        pub terminals: Vec<TerminalInfo>,
+        pub failed: Vec<ErrorPayload>,
+    }
+
+    impl Session {
+        pub fn default_path() -> PathBuf {
+            dirs::data_dir()
+                .unwrap_or_else(|| PathBuf::from("."))
+                .join("tart")
+                .join("session.json")
+        }
+
+        /// The running terminals worth bringing back, leaving out playbacks and exited programs.
+        pub fn capture(terminals: &TerminalManager, lines: usize) -> Session {
+            let terminals = terminals
+                .list_terminals()
+                .into_iter()
+                .filter(|info| info.kind == TerminalKind::Pty)
+                .filter(|info| matches!(info.state, TerminalState::Running))
+                .map(|info| SavedTerminal {
+                    contents: if lines > 0 { terminals.tail(info.id, lines).ok() } else { None },
+                    info,
+                })
+                .collect();
+            let saved_at = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_millis() as u64)
+                .unwrap_or_default();
+            Session { saved_at, terminals }
+        }
+
+        /// Reads the saved session, `None` if there isn't one.
+        pub fn load(path: &Path) -> Result<Option<Session>, TartError> {
+            match fs::read_to_string(path) {
+                Ok(text) => serde_json::from_str(&text).map(Some).context(ParseSessionSnafu { path }),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(ReadFileSnafu { path }.into_error(e)),
+            }
+        }
+
+        /// Writes the session, or clears the saved one when there's nothing to bring back.
+        pub fn save(&self, path: &Path) -> Result<(), TartError> {
+            if self.terminals.is_empty() {
+                return Session::discard(path);
+            }
+            let text = serde_json::to_string_pretty(self).context(SerializationSnafu { what: "session" })?;
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).context(WriteFileSnafu { path: parent })?;
+            }
+            let mut options = OpenOptions::new();
+            options.write(true).create(true).truncate(true);
+            #[cfg(unix)]
+            options.mode(0o600);
+            let mut file = options.open(path).context(WriteFileSnafu { path })?;
+
+            // `mode` only applies to a file that didn't exist yet
+            #[cfg(unix)]
+            file.set_permissions(fs::Permissions::from_mode(0o600)).context(WriteFileSnafu { path })?;
+            file.write_all(text.as_bytes()).context(WriteFileSnafu { path })
+        }
+
+        pub fn discard(path: &Path) -> Result<(), TartError> {
+            match fs::remove_file(path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(WriteFileSnafu { path }.into_error(e)),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    /// `dir` or its closest existing parent, short of the filesystem root.
+    fn existing_dir(dir: &str) -> Option<String> {
+        Path::new(dir)
+            .ancestors()
+            .filter(|dir| dir.parent().is_some())
+            .find(|dir| dir.is_dir())
+            .map(|dir| dir.to_string_lossy().to_string())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::env;
+
+        use uuid::Uuid;
+
+        use super::*;
+
+        fn scratch() -> PathBuf {
+            let dir = env::temp_dir().join(format!("tart-test-{}", Uuid::new_v4()));
+            fs::create_dir_all(&dir).unwrap();
+            dir
+        }
+
+        #[test]
+        fn missing_directory_falls_back_to_closest_parent() {
+            let dir = scratch();
+            let gone = dir.join("gone").join("deeper");
+            assert_eq!(existing_dir(gone.to_str().unwrap()), Some(dir.to_string_lossy().to_string()));
+            assert_eq!(existing_dir(dir.to_str().unwrap()), Some(dir.to_string_lossy().to_string()));
+
+            // The root is never picked, the default directory is better than that
+            let nowhere = format!("/tart-test-{}", Uuid::new_v4());
+            assert_eq!(existing_dir(&nowhere), None);
+        }
+
+        #[test]
+        fn empty_session_clears_the_saved_one() {
+            let path = scratch().join("session.json");
+            fs::write(&path, "{}").unwrap();
+            Session::default().save(&path).unwrap();
+            assert!(!path.exists());
+            assert!(Session::load(&path).unwrap().is_none());
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn capture_save_and_restore() {
+            use std::{collections::HashMap, thread, time::Duration};
+
+            use crossbeam_channel::unbounded;
+
+            use crate::common::{event_bus::EventBus, tartd::Clients};
+
+            let dir = scratch();
+            let cwd = dir.join("work");
+            fs::create_dir_all(&cwd).unwrap();
+            let bus = EventBus::Daemon {
+                clients: Clients::default(),
+                backend: unbounded().0,
+            };
+            let mut manager = TerminalManager::new();
+            let terminal = manager
+                .create_terminal(
+                    bus.clone(),
+                    TerminalOptions {
+                        command: Some("/bin/sh".to_string()),
+                        args: Some(vec!["-c".to_string(), "echo saved; exec cat".to_string()]),
+                        cwd: Some(cwd.to_string_lossy().to_string()),
+                        env: Some(HashMap::from([("TART_TEST".to_string(), "1".to_string())])),
+                        shell_integration: Some(false),
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+            for _ in 0..100 {
+                if terminal.tail(5).contains("saved") {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(20));
+            }
+
+            let path = dir.join("session.json");
+            Session::capture(&manager, 5).save(&path).unwrap();
+            manager.remove_terminal(terminal.id());
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+            let session = Session::load(&path).unwrap().unwrap();
+            let [saved] = session.terminals.as_slice() else {
+                panic!("expected one terminal, got {:?}", session.terminals);
+            };
+            assert!(saved.contents.as_deref().unwrap_or_default().contains("saved"));
+
+            // The directory went away in between, so it starts in the parent instead
+            fs::remove_dir(&cwd).unwrap();
+            let options = saved.options();
+            assert_eq!(options.cwd, Some(dir.to_string_lossy().to_string()));
+            assert_eq!(options.env.as_ref().and_then(|env| env.get("TART_TEST")), Some(&"1".to_string()));
+
+            let restored = manager
+                .restore_terminal(bus, options, saved.info.size, saved.contents.as_deref())
+                .unwrap();
+            assert!(restored.tail(5).contains("saved"));
+            assert_eq!(restored.info().args, saved.info.args);
+            manager.remove_terminal(restored.id());
+        }
+    }
+}
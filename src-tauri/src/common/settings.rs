@@ -0,0 +1,383 @@
+pub mod config {
+    use std::{
+        collections::HashMap,
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use serde::{Deserialize, Deserializer, Serialize};
+
+    use crate::common::{
+        notifications::NotificationPolicy,
+        output::{FlushPolicy, MAX_FLUSH_INTERVAL},
+        saved_session::SessionConfig,
+        session_log::{LogFormat, LogTarget},
+    };
+
+    /// Upper bounds on scrollback so a typo can't make every terminal hoard gigabytes.
+    const MAX_SCROLLBACK_LINES: usize = 1_000_000;
+    const MAX_SCROLLBACK_BYTES: usize = 1024 * 1024 * 1024;
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    #[serde(default)]
+    pub struct FontConfig {
+        pub family: String,
+
+        #[serde(deserialize_with = "positive")]
+        pub size: f32,
+    }
+
+    impl Default for FontConfig {
+        fn default() -> Self {
+            FontConfig {
+                family: "monospace".to_string(),
+                size: 14.0,
+            }
+        }
+    }
+
+    /// Terminal colors as `#rrggbb` strings, unset entries keep the frontend's theme.
+    #[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+    #[serde(default)]
+    pub struct ColorConfig {
+        #[serde(deserialize_with = "optional_color")]
+        pub foreground: Option<String>,
+
+        #[serde(deserialize_with = "optional_color")]
+        pub background: Option<String>,
+
+        #[serde(deserialize_with = "optional_color")]
+        pub cursor: Option<String>,
+
+        #[serde(deserialize_with = "optional_color")]
+        pub selection: Option<String>,
+
+        /// The 16 ANSI colors, or empty for the defaults
+        #[serde(deserialize_with = "palette")]
+        pub palette: Vec<String>,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    #[serde(default)]
+    pub struct ScrollbackConfig {
+        #[serde(deserialize_with = "scrollback_lines")]
+        pub lines: usize,
+
+        /// Bound on the text kept per terminal, whichever of the two limits is hit first applies
+        #[serde(deserialize_with = "scrollback_bytes")]
+        pub bytes: usize,
+    }
+
+    impl Default for ScrollbackConfig {
+        fn default() -> Self {
+            ScrollbackConfig {
+                lines: 10_000,
+                bytes: 16 * 1024 * 1024,
+            }
+        }
+    }
+
+    /// Logging every terminal's output to disk, e.g. for an audit trail.
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    #[serde(default)]
+    pub struct LoggingConfig {
+        pub enabled: bool,
+
+        /// Each terminal gets its own `<time>-<id>.log` in here
+        pub directory: PathBuf,
+        pub format: LogFormat,
+
+        /// Also applies to logs turned on for a single terminal
+        pub max_bytes: u64,
+        pub keep: usize,
+    }
+
+    impl Default for LoggingConfig {
+        fn default() -> Self {
+            LoggingConfig {
+                enabled: false,
+                directory: dirs::data_dir()
+                    .unwrap_or_else(|| PathBuf::from("."))
+                    .join("tart")
+                    .join("logs"),
+                format: LogFormat::default(),
+                max_bytes: 10 * 1024 * 1024,
+                keep: 5,
+            }
+        }
+    }
+
+    impl LoggingConfig {
+        /// Where a terminal logs to with the rotation configured here.
+        pub fn target(&self, path: PathBuf, format: Option<LogFormat>) -> LogTarget {
+            LogTarget {
+                path,
+                format: format.unwrap_or(self.format),
+                max_bytes: self.max_bytes,
+                keep: self.keep,
+            }
+        }
+
+        /// The log every terminal gets when logging is on for all of them.
+        pub fn default_target(&self) -> Option<LogTarget> {
+            self.enabled
+                .then(|| self.target(self.directory.join("{time}-{id}.log"), None))
+        }
+    }
+
+    /// The contents of `tart.toml`.
+    #[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+    #[serde(default)]
+    pub struct TartConfig {
+        pub font: FontConfig,
+        pub colors: ColorConfig,
+        pub default_profile: Option<String>,
+        pub scrollback: ScrollbackConfig,
+
+        /// Action name to key combination, e.g. `copy = "ctrl+shift+c"`
+        pub keybindings: HashMap<String, String>,
+
+        #[serde(deserialize_with = "flush_policy")]
+        pub flush: FlushPolicy,
+
+        /// Whether terminals load tart's shell integration unless their options say otherwise
+        pub shell_integration: bool,
+        pub notifications: NotificationPolicy,
+        pub logging: LoggingConfig,
+        pub session: SessionConfig,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    pub struct ConfigError {
+        pub message: String,
+        pub line: Option<usize>,
+        pub column: Option<usize>,
+    }
+
+    impl ConfigError {
+        fn new(message: String) -> Self {
+            ConfigError {
+                message,
+                line: None,
+                column: None,
+            }
+        }
+    }
+
+    impl TartConfig {
+        pub fn default_path() -> PathBuf {
+            dirs::config_dir()
+                .unwrap_or_else(|| PathBuf::from("."))
+                .join("tart")
+                .join("tart.toml")
+        }
+
+        /// Parses and validates a config file, locating errors as 1-based line/column.
+        pub fn parse(content: &str) -> Result<Self, ConfigError> {
+            toml::from_str::<TartConfig>(content).map_err(|e| {
+                let (line, column) = match e.span() {
+                    Some(span) => {
+                        let before = &content[..span.start.min(content.len())];
+                        let line = before.matches('\n').count() + 1;
+                        let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+                        (Some(line), Some(column))
+                    }
+                    None => (None, None),
+                };
+                ConfigError {
+                    message: e.message().to_string(),
+                    line,
+                    column,
+                }
+            })
+        }
+
+        /// Loads the config at `path`, writing out the defaults if it doesn't exist yet.
+        pub fn load(path: &Path) -> Result<Self, ConfigError> {
+            if !path.exists() {
+                let config = TartConfig::default();
+                config.save(path)?;
+                return Ok(config);
+            }
+
+            let content = fs::read_to_string(path).map_err(|e| {
+                ConfigError::new(format!("Failed to read {}: {}", path.display(), e))
+            })?;
+            TartConfig::parse(&content)
+        }
+
+        fn save(&self, path: &Path) -> Result<(), ConfigError> {
+            let content = toml::to_string_pretty(self)
+                .map_err(|e| ConfigError::new(format!("Failed to serialize config: {}", e)))?;
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(|e| {
+                    ConfigError::new(format!("Failed to create {}: {}", parent.display(), e))
+                })?;
+            }
+            fs::write(path, content).map_err(|e| {
+                ConfigError::new(format!("Failed to write {}: {}", path.display(), e))
+            })
+        }
+    }
+
+    fn positive<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+        let value = f32::deserialize(deserializer)?;
+        if value > 0.0 {
+            Ok(value)
+        } else {
+            Err(serde::de::Error::custom(format!("expected a positive number, got {}", value)))
+        }
+    }
+
+    fn scrollback_lines<'de, D: Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+        let value = usize::deserialize(deserializer)?;
+        if value <= MAX_SCROLLBACK_LINES {
+            Ok(value)
+        } else {
+            Err(serde::de::Error::custom(format!(
+                "scrollback may be at most {} lines, got {}",
+                MAX_SCROLLBACK_LINES, value
+            )))
+        }
+    }
+
+    fn scrollback_bytes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+        let value = usize::deserialize(deserializer)?;
+        if value <= MAX_SCROLLBACK_BYTES {
+            Ok(value)
+        } else {
+            Err(serde::de::Error::custom(format!(
+                "scrollback may be at most {} bytes, got {}",
+                MAX_SCROLLBACK_BYTES, value
+            )))
+        }
+    }
+
+    fn flush_policy<'de, D: Deserializer<'de>>(deserializer: D) -> Result<FlushPolicy, D::Error> {
+        let value = FlushPolicy::deserialize(deserializer)?;
+        if value.interval_ms > MAX_FLUSH_INTERVAL.as_millis() as u64 {
+            return Err(serde::de::Error::custom(format!(
+                "the flush interval may be at most {} ms, got {}",
+                MAX_FLUSH_INTERVAL.as_millis(),
+                value.interval_ms
+            )));
+        }
+        if value.max_bytes == 0 {
+            return Err(serde::de::Error::custom("max_bytes must be at least 1"));
+        }
+        Ok(value)
+    }
+
+    fn is_color(value: &str) -> bool {
+        value.len() == 7
+            && value.starts_with('#')
+            && value[1..].chars().all(|c| c.is_ascii_hexdigit())
+    }
+
+    fn optional_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        if is_color(&value) {
+            Ok(Some(value))
+        } else {
+            Err(serde::de::Error::custom(format!("expected a #rrggbb color, got {:?}", value)))
+        }
+    }
+
+    fn palette<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+        let value = Vec::<String>::deserialize(deserializer)?;
+        if !value.is_empty() && value.len() != 16 {
+            return Err(serde::de::Error::custom(format!(
+                "the palette needs exactly 16 colors, got {}",
+                value.len()
+            )));
+        }
+        if let Some(invalid) = value.iter().find(|c| !is_color(c)) {
+            return Err(serde::de::Error::custom(format!(
+                "expected a #rrggbb color, got {:?}",
+                invalid
+            )));
+        }
+        Ok(value)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /// Parses `content`, expecting it to fail.
+        fn error(content: &str) -> ConfigError {
+            TartConfig::parse(content).unwrap_err()
+        }
+
+        #[test]
+        fn empty_file_is_the_defaults() {
+            assert_eq!(TartConfig::parse("").unwrap(), TartConfig::default());
+        }
+
+        #[test]
+        fn values_override_defaults() {
+            let config = TartConfig::parse(
+                "default_profile = \"zsh\"\n\
+                 [font]\nsize = 12.5\n\
+                 [scrollback]\nlines = 500\n\
+                 [flush]\ninterval_ms = 0\n",
+            )
+            .unwrap();
+            assert_eq!(config.default_profile.as_deref(), Some("zsh"));
+            assert_eq!(config.font.size, 12.5);
+            assert_eq!(config.font.family, FontConfig::default().family);
+            assert_eq!(config.scrollback.lines, 500);
+            assert_eq!(config.scrollback.bytes, ScrollbackConfig::default().bytes);
+            assert_eq!(config.flush.interval_ms, 0);
+        }
+
+        #[test]
+        fn syntax_error_is_located() {
+            let e = error("[font]\nfamily = \"mono\"\nsize = = 3\n");
+            assert_eq!((e.line, e.column), (Some(3), Some(8)));
+        }
+
+        #[test]
+        fn invalid_value_is_located() {
+            let e = error("[font]\nsize = -1.0\n");
+            assert!(e.message.contains("positive"), "{}", e.message);
+            assert_eq!((e.line, e.column), (Some(2), Some(8)));
+        }
+
+        #[test]
+        fn columns_count_characters_not_bytes() {
+            let e = error("[colors]\n# ünïcödé\nforeground = \"über\"\n");
+            assert_eq!((e.line, e.column), (Some(3), Some(14)));
+        }
+
+        #[test]
+        fn colors_are_checked() {
+            assert!(TartConfig::parse("[colors]\ncursor = \"#00ff7f\"\n").is_ok());
+            assert!(error("[colors]\ncursor = \"green\"\n").message.contains("#rrggbb"));
+            assert!(error("[colors]\npalette = [\"#000000\"]\n").message.contains("16 colors"));
+        }
+
+        #[test]
+        fn limits_are_enforced() {
+            // The flush policy is checked as a whole, so its errors point at the table
+            let rejected = [
+                ("[scrollback]\nlines = 1000001\n", 2),
+                ("[scrollback]\nbytes = 1073741825\n", 2),
+                ("[flush]\ninterval_ms = 1001\n", 1),
+                ("[flush]\nmax_bytes = 0\n", 1),
+            ];
+            for (content, line) in rejected {
+                let e = error(content);
+                assert_eq!(e.line, Some(line), "{}: {}", content, e.message);
+            }
+            assert!(TartConfig::parse("[scrollback]\nbytes = 1073741824\n").is_ok());
+            assert!(TartConfig::parse("[flush]\ninterval_ms = 1000\n").is_ok());
+        }
+
+        #[test]
+        fn wrong_type_is_located() {
+            let e = error("keybindings = 3\n");
+            assert_eq!(e.line, Some(1));
+        }
+    }
+}
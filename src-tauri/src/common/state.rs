@@ -1,26 +1,89 @@
 pub mod app_state {
-    use std::sync::{Arc, Mutex};
+    use std::{
+        path::PathBuf,
+        sync::{Arc, Mutex, MutexGuard},
+        thread,
+    };
+
+    use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 
     use portable_pty::PtySize;
     use serde::{Deserialize, Serialize};
     use serde_json::Value;
-    use tauri::{AppHandle, Emitter, Listener, Manager};
+    use snafu::{OptionExt, ResultExt};
+    use tauri::{ipc::Channel, AppHandle, Listener, Manager};
     use uuid::Uuid;
 
-    use crate::common::term::{PtySizeDef, TerminalCommand, TerminalInfo, TerminalManager, TerminalMessage};
+    use crate::common::asciicast::PlaybackStatus;
+    use crate::common::config::{ConfigError, TartConfig};
+    use crate::common::error::{ErrorPayload, LockPoisonedSnafu, SerializationSnafu, TartError, UnknownProfileSnafu, UnknownTerminalSnafu};
+    use crate::common::event_bus::EventBus;
+    use crate::common::notifications::{AttentionReason, NotificationPolicy};
+    use crate::common::output::{FlushPolicy, OutputEncoding};
+    use crate::common::profile::{Profile, ProfileStore};
+    use crate::common::saved_session::{RestoredSession, Session};
+    use crate::common::session_log::LogFormat;
+    use crate::common::shell::ShellInfo;
+    use crate::common::term::{ExitInfo, PtySizeDef, Subscription, TerminalCommand, TerminalInfo, TerminalManager, TerminalOptions};
 
     #[derive(Clone, Debug)]
     pub struct AppState {
-        pub terminals: Arc<Mutex<TerminalManager>>
+        pub terminals: Arc<Mutex<TerminalManager>>,
+        pub profiles: Arc<Mutex<ProfileStore>>,
+        pub config: Arc<Mutex<TartConfig>>,
+        config_path: PathBuf,
+        config_watcher: Arc<Mutex<Option<RecommendedWatcher>>>,
+        session_path: PathBuf
     }
 
     #[derive(Serialize, Deserialize, Clone, Debug)]
     #[serde(tag = "type")]
     pub enum AppCommand {
-        CreateTerminal{command: String, args: Option<Vec<String>>, title: Option<String>},
+        CreateTerminal{
+            #[serde(flatten)]
+            options: TerminalOptions
+        },
         RemoveTerminal{id: Uuid},
         WriteData{id: Uuid, data: String},
         GetTerminals{},
+        GetDefaultShell{},
+        GetConfig{},
+        SetFlushPolicy{id: Uuid, policy: FlushPolicy},
+        AckOutput{id: Uuid, bytes: usize},
+        Unsubscribe{id: Uuid, subscription: Uuid},
+        GetSnapshot{id: Uuid},
+        SetTitle{id: Uuid, title: Option<String>},
+        GetCommandHistory{id: Uuid},
+        SetNotificationPolicy{id: Uuid, policy: NotificationPolicy},
+        SetFocus{id: Option<Uuid>},
+        StartRecording{
+            id: Uuid,
+            path: PathBuf,
+
+            #[serde(default)]
+            include_input: bool
+        },
+        StopRecording{id: Uuid},
+        CreatePlayback{
+            path: PathBuf,
+
+            #[serde(default = "default_speed")]
+            speed: f64,
+            idle_time_limit: Option<f64>
+        },
+        PausePlayback{id: Uuid},
+        ResumePlayback{id: Uuid},
+        SeekPlayback{id: Uuid, position: f64},
+        SetPlaybackSpeed{id: Uuid, speed: f64},
+        SetLogging{id: Uuid, path: Option<PathBuf>, format: Option<LogFormat>},
+        GetSavedSession{},
+        RestoreSession{},
+        DiscardSession{},
+        ListProfiles{},
+        CreateTerminalFromProfile{profile: String, overrides: Option<TerminalOptions>},
+        CreateProfile{profile: Profile},
+        UpdateProfile{name: String, profile: Profile},
+        DeleteProfile{name: String},
         Resize{
             id: Uuid, 
 
@@ -29,6 +92,10 @@ pub mod app_state {
         }
     }
 
+    fn default_speed() -> f64 {
+        1.0
+    }
+
     impl AppCommand {
         pub fn wrap(&self) -> AppCommandWrapper {
             AppCommandWrapper {
@@ -45,28 +112,44 @@ pub mod app_state {
     }
 
     impl AppCommandWrapper {
-        pub fn emit_result(&self, app: &AppHandle, result: Result<impl Serialize, impl Serialize>) -> () {
+        pub fn result(&self, result: Result<impl Serialize, impl Serialize>) -> CommandResult {
+            // A value that can't be serialized is reported as a serialization error in its place
+            let failed = |e: TartError| serde_json::to_value(e).unwrap_or(Value::Null);
             let serialized = match result {
-                Ok(v) => Ok(serde_json::to_value(v).or::<()>(Ok(serde_json::to_value("Failed to serialize successful value.").unwrap())).unwrap()),
-                Err(v) => Err(serde_json::to_value(v).or::<()>(Ok(serde_json::to_value("Failed to serialize error value.").unwrap())).unwrap())
+                Ok(v) => serde_json::to_value(v).context(SerializationSnafu { what: "command result" }).map_err(failed),
+                Err(v) => Err(serde_json::to_value(v).context(SerializationSnafu { what: "command error" }).unwrap_or_else(failed))
             };
 
-            let res = CommandResult {
+            CommandResult {
                 id: self.id.clone(),
                 command: self.command.clone(),
                 result: serialized
-            };
-
-            let _ = app.emit("tart://result", res);
+            }
         }
     }
 
     #[derive(Serialize, Deserialize, Clone, Debug)]
     #[serde(tag = "type")]
     pub enum FrontendEvent {
-        TerminalRead{id: Uuid, data: String},
+        TerminalRead{id: Uuid, data: String, encoding: OutputEncoding, bytes: usize},
         TerminalCreated{id: Uuid},
         TerminalRemoved{id: Uuid},
+        TerminalExited{id: Uuid, status: ExitInfo},
+        TerminalTitleChanged{id: Uuid, title: Option<String>},
+        TerminalCwdChanged{id: Uuid, cwd: String},
+
+        /// Writing to or resizing the terminal failed, which has no caller to go back to
+        TerminalError{id: Uuid, error: ErrorPayload},
+
+        /// A command reported through shell integration finished, `duration` is in milliseconds
+        CommandFinished{id: Uuid, command: String, exit_code: Option<i32>, duration: u64},
+
+        /// The terminal wants the user to look at it, e.g. to badge its tab
+        Attention{id: Uuid, reason: AttentionReason},
+        PlaybackProgress{id: Uuid, status: PlaybackStatus},
+        ProfilesChanged{},
+        ConfigChanged{config: TartConfig},
+        ConfigInvalid{error: ConfigError},
         TerminalResized{
             id: Uuid,
             
@@ -78,7 +161,7 @@ pub mod app_state {
     #[derive(Serialize, Deserialize, Clone, Debug)]
     #[serde(tag = "type")]
     pub enum BackendEvent {
-        TerminalRead{id: Uuid, data: String}
+        TerminalExited{id: Uuid, status: ExitInfo}
     }
 
     #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -88,114 +171,412 @@ pub mod app_state {
         result: Result<Value, Value>
     }
 
+    impl CommandResult {
+        pub fn result(&self) -> &Result<Value, Value> {
+            &self.result
+        }
+    }
+
     impl AppState {
         pub fn new() -> Self {
-            AppState {terminals: Arc::new(Mutex::new(TerminalManager::new()))}
-        }
+            let path = ProfileStore::default_path();
+            let profiles = ProfileStore::load(&path).unwrap_or_else(|e| {
+                println!("{}", e);
+                ProfileStore::unreadable(&path, &e)
+            });
 
-        pub fn emit_event(&self, app: &AppHandle, event: FrontendEvent) -> () {
-            let _ = app.emit("tart://event", event);
-        }
+            let config_path = TartConfig::default_path();
+            let config = TartConfig::load(&config_path).unwrap_or_else(|e| {
+                println!("{:?}", e);
+                TartConfig::default()
+            });
 
-        pub fn run_in_background(&self, app: &AppHandle) -> () {
-            let handle = app.app_handle().clone();
-            app.listen("tart://internal", move |evt| {
-                if let Ok(event) = serde_json::from_str::<BackendEvent>(evt.payload()) {
-                    let _state = handle.state::<AppState>();
+            AppState {
+                terminals: Arc::new(Mutex::new(TerminalManager::new())),
+                profiles: Arc::new(Mutex::new(profiles)),
+                config: Arc::new(Mutex::new(config)),
+                config_path,
+                config_watcher: Arc::new(Mutex::new(None)),
+                session_path: Session::default_path()
+            }
+        }
 
-                    match event {
-                        BackendEvent::TerminalRead { id, data } => {
-                            let _ = handle.emit("tart://event", FrontendEvent::TerminalRead{id, data});
+        /// Fills in defaults that come from the config file rather than the terminal itself.
+        /// Options that name neither a program nor a profile start from the default profile.
+        pub fn terminal_options(&self, options: TerminalOptions) -> TerminalOptions {
+            // Read on its own, command handling takes the profiles before the config
+            let default_profile = self.config.lock().unwrap().default_profile.clone();
+            let mut options = match default_profile {
+                Some(name) if options.command.is_none() && options.profile.is_none() => {
+                    let found = self
+                        .lock_profiles()
+                        .and_then(|profiles| profiles.get(&name).context(UnknownProfileSnafu { name: &name }));
+                    match found {
+                        Ok(profile) => profile.options(Some(options)),
+                        Err(e) => {
+                            println!("Ignoring the default profile: {}", e);
+                            options
                         }
                     }
+                },
+                _ => options
+            };
+
+            let config = self.config.lock().unwrap();
+            options.from_config.flush_policy = options.flush_policy.is_none();
+            options.from_config.scrollback = options.scrollback.is_none();
+            options.flush_policy.get_or_insert(config.flush);
+            options.scrollback.get_or_insert_with(|| config.scrollback.clone());
+            options.shell_integration.get_or_insert(config.shell_integration);
+            options.notifications.get_or_insert(config.notifications);
+            if options.log.is_none() {
+                options.log = config.logging.default_target();
+            }
+            options
+        }
+
+        /// Watches the config file and applies valid changes without restarting.
+        pub fn watch_config(&self, bus: &EventBus) -> () {
+            let state = self.clone();
+            let bus = bus.clone();
+            let watched = self.config_path.clone();
+            let watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+                if let Ok(event) = res {
+                    // Editors often save by replacing the file, so watch the directory and match by name
+                    let relevant = (event.kind.is_modify() || event.kind.is_create())
+                        && event.paths.iter().any(|p| p.file_name() == watched.file_name());
+                    if relevant {
+                        state.reload_config(&bus);
+                    }
                 }
             });
 
-            let handle = app.app_handle().clone();
-            app.listen("tart://command", move |evt| {
-                if let Ok(event) = serde_json::from_str::<AppCommandWrapper>(evt.payload()) {
-                    let state = handle.state::<AppState>();
-                    let mut terminals = state.terminals.lock().unwrap();
-                    match event.command {
-                        AppCommand::CreateTerminal { ref command, ref args, ref title } => {
-                            let result = terminals.create_terminal(handle.clone(), command.clone(), args.clone(), title.clone()).and_then(|t| Ok(t.info()));
-                            event.emit_result(&handle, result.clone());
-                            if let Ok(created) = result {
-                                state.emit_event(&handle, FrontendEvent::TerminalCreated { id: created.id });
-                            }
-
-                        },
-                        AppCommand::WriteData { id, ref data } => {
-                            println!("Writing {:?} to {:?}", data.clone(), id);
-                            match terminals.sender(id) {
-                                Some(sender) => match sender.send(TerminalMessage::new(TerminalCommand::Write(data.clone()))) {
-                                    Ok(_) => {
-                                        event.emit_result(&handle, Ok::<&str, &str>("Wrote data"));
-                                    },
-                                    Err(_) => {
-                                        event.emit_result(&handle, Err::<&str, &str>("Failed to send data to terminal instance"));
-                                    }
-                                },
-                                None => {
-                                    event.emit_result(&handle, Err::<&str, &str>("Unknown terminal ID"));
-                                }
-                            }
-                        },
-                        AppCommand::GetTerminals {  } => {event.emit_result(&handle, Ok::<Vec<TerminalInfo>, ()>(terminals.list_terminals()));},
-                        AppCommand::Resize { id, size } => {
-                            match terminals.sender(id) {
-                                Some(sender) => match sender.send(TerminalMessage::new(TerminalCommand::Resize { size: size.clone() })) {
-                                    Ok(_) => {
-                                        event.emit_result(&handle, Ok::<&str, &str>("Resized terminal"));
-                                        state.emit_event(&handle, FrontendEvent::TerminalResized { id, size });
-                                    },
-                                    Err(_) => event.emit_result(&handle, Err::<&str, &str>("Failed to send terminal resize command"))
-                                },
-                                None => event.emit_result(&handle, Err::<&str, &str>("Unknown terminal ID"))
-                            }
-                        },
-                        AppCommand::RemoveTerminal { id } => {
-                            if let Some(_) = terminals.terminal(id) {
-                                terminals.remove_terminal(id);
-                                event.emit_result(&handle, Ok::<&str, &str>("Removed terminal"));
-                                state.emit_event(&handle, FrontendEvent::TerminalRemoved { id });
-                            } else {
-                                event.emit_result(&handle, Err::<&str, &str>("Unknown terminal ID"));
-                            }
+            match watcher {
+                Ok(mut watcher) => {
+                    if let Some(dir) = self.config_path.parent() {
+                        if let Err(e) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+                            println!("Failed to watch {}: {:?}", dir.display(), e);
                         }
                     }
+                    *self.config_watcher.lock().unwrap() = Some(watcher);
+                },
+                Err(e) => println!("Failed to create config watcher: {:?}", e)
+            }
+        }
+
+        pub fn reload_config(&self, bus: &EventBus) -> () {
+            match TartConfig::load(&self.config_path) {
+                Ok(config) => {
+                    let previous = {
+                        let mut current = self.config.lock().unwrap();
+                        if *current == config {
+                            return;
+                        }
+                        std::mem::replace(&mut *current, config.clone())
+                    };
+
+                    // The config lock is released first, command handling takes these two the other way round
+                    // Terminals that were given their own settings keep them
+                    if previous.flush != config.flush {
+                        self.terminals.lock().unwrap().config_flush_changed(config.flush);
+                    }
+                    if previous.scrollback != config.scrollback {
+                        self.terminals.lock().unwrap().config_scrollback_changed(&config.scrollback);
+                    }
+                    self.emit_event(bus, FrontendEvent::ConfigChanged { config });
+                },
+                Err(error) => self.emit_event(bus, FrontendEvent::ConfigInvalid { error })
+            }
+        }
+
+        pub fn emit_event(&self, bus: &EventBus, event: FrontendEvent) -> () {
+            bus.emit(event);
+        }
+
+        pub fn run_in_background(&self, app: &AppHandle) -> () {
+            let state = self.clone();
+            let bus = EventBus::App(app.app_handle().clone());
+            app.listen("tart://internal", move |evt| {
+                if let Ok(event) = serde_json::from_str::<BackendEvent>(evt.payload()) {
+                    state.handle_backend_event(&bus, event);
                 }
             });
+
         }
 
-        pub async fn run_command(&self, app: &AppHandle, command: AppCommand) -> CommandResult {
-            let message = command.wrap();
-            let (tx, mut rx) = tokio::sync::mpsc::channel::<CommandResult>(1);
-            let msgclone = message.clone();
-            app.listen("tart://result", move |evt| {
-                if let Ok(result) = serde_json::from_str::<CommandResult>(evt.payload()) {
-                    if result.id.to_string() == msgclone.id.to_string() {
-                        let _ = tx.try_send(result);
+        pub fn handle_backend_event(&self, bus: &EventBus, event: BackendEvent) {
+            match event {
+                BackendEvent::TerminalExited { id, status } => {
+                    self.emit_event(bus, FrontendEvent::TerminalExited{id, status});
+
+                    // Listeners run on the emitting thread, which is the terminal's own loop, so reap elsewhere
+                    let state = self.clone();
+                    let bus = bus.clone();
+                    thread::spawn(move || {
+                        let removed = state.terminals.lock().unwrap().reap_terminal(id);
+                        if removed {
+                            state.emit_event(&bus, FrontendEvent::TerminalRemoved { id });
+                        }
+                    });
+                }
+            }
+        }
+
+        /// Executes a command directly against the app's state. Only the side effects other
+        /// windows care about go out as events; the result goes straight back to the caller.
+        pub fn run_command(&self, bus: &EventBus, command: AppCommand) -> CommandResult {
+            let event = command.wrap();
+            let mut terminals = match self.terminals.lock() {
+                Ok(terminals) => terminals,
+                Err(_) => return event.result(Err::<(), _>(LockPoisonedSnafu { resource: "terminals" }.build()))
+            };
+            match event.command {
+                AppCommand::CreateTerminal { ref options } => {
+                    let result = terminals.create_terminal(bus.clone(), self.terminal_options(options.clone())).map(|t| t.info());
+                    if let Ok(created) = &result {
+                        self.emit_event(bus, FrontendEvent::TerminalCreated { id: created.id });
+                    }
+                    event.result(result)
+                },
+                AppCommand::WriteData { id, ref data } => {
+                    event.result(terminals.send(id, TerminalCommand::Write(data.clone())).map(|_| "Wrote data"))
+                },
+                AppCommand::GetTerminals {  } => event.result(Ok::<Vec<TerminalInfo>, ()>(terminals.list_terminals())),
+                AppCommand::GetDefaultShell {  } => event.result(Ok::<ShellInfo, ()>(ShellInfo::resolve())),
+                AppCommand::GetConfig {  } => event.result(self.lock_config().map(|config| config.clone())),
+                AppCommand::Resize { id, size } => {
+                    let result = terminals.send(id, TerminalCommand::Resize { size: size.clone() }).map(|_| "Resized terminal");
+                    if result.is_ok() {
+                        self.emit_event(bus, FrontendEvent::TerminalResized { id, size });
+                    }
+                    event.result(result)
+                },
+                AppCommand::SetFlushPolicy { id, policy } => {
+                    event.result(terminals.set_flush_policy(id, policy).map(|_| "Updated flush policy"))
+                },
+                AppCommand::AckOutput { id, bytes } => {
+                    event.result(terminals.send(id, TerminalCommand::Ack { bytes }).map(|_| "Acknowledged output"))
+                },
+                AppCommand::GetSnapshot { id } => event.result(terminals.snapshot(id)),
+                AppCommand::GetCommandHistory { id } => event.result(terminals.command_history(id)),
+                AppCommand::SetNotificationPolicy { id, policy } => event.result(terminals.set_notifications(id, policy)),
+                AppCommand::SetFocus { id } => event.result(terminals.set_focus(id).map(|_| "Updated focus")),
+                AppCommand::StartRecording { id, ref path, include_input } => {
+                    event.result(terminals.start_recording(id, path, include_input))
+                },
+                AppCommand::StopRecording { id } => event.result(terminals.stop_recording(id)),
+                AppCommand::SetLogging { id, ref path, format } => {
+                    // Rotation always follows the config, only where and how is up to the caller
+                    let target = match self.lock_config() {
+                        Ok(config) => path.clone().map(|path| config.logging.target(path, format)),
+                        Err(e) => return event.result(Err::<(), _>(e))
+                    };
+                    event.result(terminals.set_logging(id, target.as_ref()))
+                },
+                AppCommand::GetSavedSession {  } => event.result(Session::load(&self.session_path)),
+                AppCommand::RestoreSession {  } => event.result(self.restore_session(bus, &mut terminals)),
+                AppCommand::DiscardSession {  } => {
+                    event.result(Session::discard(&self.session_path).map(|_| "Discarded session"))
+                },
+                AppCommand::CreatePlayback { ref path, speed, idle_time_limit } => {
+                    let result = terminals.create_playback(bus.clone(), path, speed, idle_time_limit).map(|t| t.info());
+                    if let Ok(created) = &result {
+                        self.emit_event(bus, FrontendEvent::TerminalCreated { id: created.id });
+                    }
+                    event.result(result)
+                },
+                AppCommand::PausePlayback { id } => {
+                    event.result(terminals.control_playback(id, TerminalCommand::Pause).map(|_| "Paused playback"))
+                },
+                AppCommand::ResumePlayback { id } => {
+                    event.result(terminals.control_playback(id, TerminalCommand::Resume).map(|_| "Resumed playback"))
+                },
+                AppCommand::SeekPlayback { id, position } => {
+                    event.result(terminals.control_playback(id, TerminalCommand::Seek { position }).map(|_| "Moved playback"))
+                },
+                AppCommand::SetPlaybackSpeed { id, speed } => {
+                    event.result(terminals.control_playback(id, TerminalCommand::SetSpeed { speed }).map(|_| "Updated playback speed"))
+                },
+                AppCommand::SetTitle { id, ref title } => {
+                    let result = terminals.set_title(id, title.clone());
+                    if let Ok(info) = &result {
+                        self.emit_event(bus, FrontendEvent::TerminalTitleChanged { id, title: info.display_title() });
+                    }
+                    event.result(result)
+                },
+                AppCommand::Unsubscribe { id, subscription } => {
+                    event.result(terminals.unsubscribe(id, subscription).map(|removed| if removed { "Unsubscribed" } else { "Not subscribed" }))
+                },
+                AppCommand::RemoveTerminal { id } => {
+                    if let Some(_) = terminals.terminal(id) {
+                        terminals.remove_terminal(id);
+                        self.emit_event(bus, FrontendEvent::TerminalRemoved { id });
+                        event.result(Ok::<&str, ()>("Removed terminal"))
+                    } else {
+                        event.result(Err::<(), _>(UnknownTerminalSnafu { id }.build()))
+                    }
+                },
+                AppCommand::ListProfiles {  } => event.result(self.lock_profiles().and_then(|mut profiles| profiles.check().map(|_| profiles.list()))),
+                AppCommand::CreateTerminalFromProfile { ref profile, ref overrides } => {
+                    let result = self
+                        .lock_profiles()
+                        .and_then(|profiles| profiles.get(profile).context(UnknownProfileSnafu { name: profile }))
+                        .and_then(|found| terminals.create_terminal(bus.clone(), self.terminal_options(found.options(overrides.clone()))))
+                        .map(|t| t.info());
+                    if let Ok(created) = &result {
+                        self.emit_event(bus, FrontendEvent::TerminalCreated { id: created.id });
                     }
+                    event.result(result)
+                },
+                AppCommand::CreateProfile { ref profile } => {
+                    let result = self.lock_profiles().and_then(|mut profiles| profiles.create(profile.clone()));
+                    self.profile_result(bus, &event, result)
+                },
+                AppCommand::UpdateProfile { ref name, ref profile } => {
+                    let result = self.lock_profiles().and_then(|mut profiles| profiles.update(name, profile.clone()));
+                    self.profile_result(bus, &event, result)
+                },
+                AppCommand::DeleteProfile { ref name } => {
+                    let result = self.lock_profiles().and_then(|mut profiles| profiles.delete(name));
+                    self.profile_result(bus, &event, result)
                 }
-            });
-            if let Err(_) = app.emit("tart://command", message.clone()) {
-                return CommandResult {
-                    id: message.clone().id,
-                    command: message.clone().command,
-                    result: Err(serde_json::to_value("Failed to emit event.").unwrap())
-                };
             }
+        }
+
+        /// Saves the open terminals for the next launch to restore, unless the config says not to.
+        pub fn save_session(&self) -> Result<(), TartError> {
+            let config = self.lock_config()?.session.clone();
+            if !config.save {
+                return Ok(());
+            }
+            let terminals = self.terminals.lock().map_err(|_| LockPoisonedSnafu { resource: "terminals" }.build())?;
+            Session::capture(&terminals, config.scrollback_lines).save(&self.session_path)
+        }
 
-            if let Some(result) = rx.recv().await {
-                result
-            } else {
-                CommandResult {
-                    id: message.id,
-                    command: message.command,
-                    result: Err(serde_json::to_value("Unexpected error receiving response").unwrap())
+        /// Starts the saved terminals over in their old order. One that fails to start doesn't
+        /// stop the rest, and the saved session is cleared either way so it's only offered once.
+        fn restore_session(&self, bus: &EventBus, terminals: &mut TerminalManager) -> Result<RestoredSession, TartError> {
+            let session = Session::load(&self.session_path)?.unwrap_or_default();
+            let mut restored = RestoredSession::default();
+            for saved in session.terminals {
+                let options = self.terminal_options(saved.options());
+                let result = terminals
+                    .restore_terminal(bus.clone(), options, saved.info.size, saved.contents.as_deref())
+                    .map(|t| t.set_user_title(saved.info.user_title.clone()));
+                match result {
+                    Ok(info) => {
+                        self.emit_event(bus, FrontendEvent::TerminalCreated { id: info.id });
+                        restored.terminals.push(info);
+                    },
+                    Err(e) => restored.failed.push(e.payload())
                 }
             }
+            Session::discard(&self.session_path)?;
+            Ok(restored)
+        }
+
+        /// Subscriptions carry a webview channel, which can only come in as a tauri command argument.
+        pub fn subscribe(&self, id: Uuid, channel: Channel<FrontendEvent>) -> Result<Subscription, TartError> {
+            self.terminals
+                .lock()
+                .map_err(|_| LockPoisonedSnafu { resource: "terminals" }.build())?
+                .subscribe(id, channel)
+        }
+
+        fn profile_result(&self, bus: &EventBus, event: &AppCommandWrapper, result: Result<Profile, TartError>) -> CommandResult {
+            if result.is_ok() {
+                self.emit_event(bus, FrontendEvent::ProfilesChanged {});
+            }
+            event.result(result)
+        }
+
+        fn lock_profiles(&self) -> Result<MutexGuard<'_, ProfileStore>, TartError> {
+            self.profiles.lock().map_err(|_| LockPoisonedSnafu { resource: "profiles" }.build())
+        }
+
+        fn lock_config(&self) -> Result<MutexGuard<'_, TartConfig>, TartError> {
+            self.config.lock().map_err(|_| LockPoisonedSnafu { resource: "config" }.build())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::{env, fs, path::Path};
+
+        use crossbeam_channel::unbounded;
+
+        use super::*;
+        use crate::common::term::Terminal;
+
+        /// State whose files would live in a scratch directory rather than the user's own.
+        fn state(dir: &Path) -> AppState {
+            AppState {
+                terminals: Arc::new(Mutex::new(TerminalManager::new())),
+                profiles: Arc::new(Mutex::new(ProfileStore::empty(&dir.join("profiles.json")))),
+                config: Arc::new(Mutex::new(TartConfig::default())),
+                config_path: dir.join("tart.toml"),
+                config_watcher: Arc::new(Mutex::new(None)),
+                session_path: dir.join("session.json")
+            }
+        }
+
+        #[test]
+        fn default_profile_applies_when_nothing_is_asked_for() {
+            let dir = env::temp_dir().join(format!("tart-test-{}", Uuid::new_v4()));
+            let state = state(&dir);
+            state.config.lock().unwrap().default_profile = Some("top".to_string());
+            let profile = Profile {
+                name: "top".to_string(),
+                command: Some("top".to_string()),
+                cwd: Some("/tmp".to_string()),
+                ..Default::default()
+            };
+            state.profiles.lock().unwrap().create(profile).unwrap();
+
+            let options = state.terminal_options(TerminalOptions {
+                cwd: Some("/".to_string()),
+                ..Default::default()
+            });
+            assert_eq!(options.command.as_deref(), Some("top"));
+            assert_eq!(options.profile.as_deref(), Some("top"));
+            assert_eq!(options.cwd.as_deref(), Some("/"));
+
+            // Asking for a program, or a profile of its own, leaves the default out of it
+            let options = state.terminal_options(TerminalOptions {
+                command: Some("sh".to_string()),
+                ..Default::default()
+            });
+            assert_eq!(options.command.as_deref(), Some("sh"));
+            assert_eq!(options.profile, None);
+
+            // A default that doesn't exist is skipped
+            state.config.lock().unwrap().default_profile = Some("missing".to_string());
+            assert_eq!(state.terminal_options(TerminalOptions::default()).command, None);
+            let _ = fs::remove_dir_all(&dir);
+        }
+
+        #[test]
+        fn only_settings_left_out_follow_the_config() {
+            let dir = env::temp_dir().join(format!("tart-test-{}", Uuid::new_v4()));
+            let state = state(&dir);
+            let own = FlushPolicy {
+                interval_ms: 0,
+                ..Default::default()
+            };
+            let options = state.terminal_options(TerminalOptions {
+                flush_policy: Some(own),
+                ..Default::default()
+            });
+            assert!(!options.from_config.flush_policy);
+            assert!(options.from_config.scrollback);
+            assert_eq!(options.scrollback, Some(TartConfig::default().scrollback));
+
+            // Starting it over leaves to the config what was left to it the first time
+            let info = Terminal::new(unbounded(), options).info();
+            let again = info.options();
+            assert_eq!(again.flush_policy, Some(own));
+            assert_eq!(again.scrollback, None);
+            assert!(state.terminal_options(again).from_config.scrollback);
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,454 @@
+pub mod output {
+    use core::str;
+    use std::{
+        sync::{Arc, Condvar, Mutex},
+        time::Duration,
+    };
+
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{Deserialize, Serialize};
+
+    /// How PTY output is encoded before it leaves the backend.
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum OutputEncoding {
+        /// UTF-8 text, with invalid sequences replaced by U+FFFD
+        #[default]
+        Utf8,
+
+        /// The raw bytes as read from the PTY, base64-encoded
+        Base64,
+    }
+
+    /// Longest a flush can be put off. Output held back any longer looks like a hung terminal.
+    pub const MAX_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// When buffered PTY output is flushed to the frontend.
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+    #[serde(default)]
+    pub struct FlushPolicy {
+        /// Longest time output may sit in the buffer, 0 flushes every read immediately
+        pub interval_ms: u64,
+
+        /// Flush early once this many bytes have accumulated
+        pub max_bytes: usize,
+    }
+
+    impl Default for FlushPolicy {
+        fn default() -> Self {
+            FlushPolicy {
+                interval_ms: 8,
+                max_bytes: 32 * 1024,
+            }
+        }
+    }
+
+    impl FlushPolicy {
+        /// The flush interval, capped at [`MAX_FLUSH_INTERVAL`].
+        pub fn interval(&self) -> Duration {
+            Duration::from_millis(self.interval_ms).min(MAX_FLUSH_INTERVAL)
+        }
+    }
+
+    /// High/low watermarks for output the frontend has not acknowledged yet.
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+    #[serde(default, try_from = "Watermarks")]
+    pub struct FlowControl {
+        /// Stop reading from the PTY once this many bytes are unacknowledged
+        pub high_watermark: usize,
+
+        /// Resume reading once the unacknowledged bytes drop to this level
+        pub low_watermark: usize,
+    }
+
+    impl Default for FlowControl {
+        fn default() -> Self {
+            FlowControl {
+                high_watermark: 512 * 1024,
+                low_watermark: 64 * 1024,
+            }
+        }
+    }
+
+    impl FlowControl {
+        /// Whether reading can both pause and resume, which needs `0 < low <= high`.
+        pub fn is_valid(&self) -> bool {
+            self.low_watermark > 0 && self.low_watermark <= self.high_watermark
+        }
+    }
+
+    /// [`FlowControl`] as written, before its watermarks are checked.
+    #[derive(Deserialize)]
+    #[serde(default)]
+    struct Watermarks {
+        high_watermark: usize,
+        low_watermark: usize,
+    }
+
+    impl Default for Watermarks {
+        fn default() -> Self {
+            let FlowControl {
+                high_watermark,
+                low_watermark,
+            } = FlowControl::default();
+            Watermarks {
+                high_watermark,
+                low_watermark,
+            }
+        }
+    }
+
+    impl TryFrom<Watermarks> for FlowControl {
+        type Error = String;
+
+        fn try_from(value: Watermarks) -> Result<Self, Self::Error> {
+            let flow = FlowControl {
+                high_watermark: value.high_watermark,
+                low_watermark: value.low_watermark,
+            };
+            if flow.is_valid() {
+                Ok(flow)
+            } else {
+                Err(format!(
+                    "flow control needs 0 < low_watermark <= high_watermark, got {} and {}",
+                    flow.low_watermark, flow.high_watermark
+                ))
+            }
+        }
+    }
+
+    /// Tracks unacknowledged output and pauses the PTY reader while the frontend catches up.
+    /// Without a [`FlowControl`] config every call is a no-op and reading never pauses. Clones
+    /// share their state, so the reader, the terminal loop and its subscribers all see the same count.
+    #[derive(Clone, Debug)]
+    pub struct Backpressure {
+        state: Arc<(Mutex<FlowState>, Condvar)>,
+    }
+
+    #[derive(Debug)]
+    struct FlowState {
+        config: Option<FlowControl>,
+        unacked: usize,
+        paused: bool,
+    }
+
+    impl Backpressure {
+        pub fn new(config: Option<FlowControl>) -> Self {
+            Backpressure {
+                state: Arc::new((
+                    Mutex::new(FlowState {
+                        config,
+                        unacked: 0,
+                        paused: false,
+                    }),
+                    Condvar::new(),
+                )),
+            }
+        }
+
+        pub fn sent(&self, bytes: usize) {
+            self.update(|state| {
+                if let Some(config) = state.config {
+                    state.unacked = state.unacked.saturating_add(bytes);
+                    if state.unacked >= config.high_watermark {
+                        state.paused = true;
+                    }
+                }
+            });
+        }
+
+        pub fn ack(&self, bytes: usize) {
+            self.update(|state| {
+                if let Some(config) = state.config {
+                    state.unacked = state.unacked.saturating_sub(bytes);
+                    if state.unacked <= config.low_watermark {
+                        state.paused = false;
+                    }
+                }
+            });
+        }
+
+        /// Forgets output that will never be acknowledged, e.g. because whoever it went to has
+        /// unsubscribed, or a new subscriber starts from a snapshot that already includes it.
+        pub fn reset(&self) {
+            self.update(|state| {
+                state.unacked = 0;
+                state.paused = false;
+            });
+        }
+
+        /// Lets the reader run freely again, e.g. when the terminal shuts down.
+        pub fn release(&self) {
+            self.update(|state| {
+                state.config = None;
+                state.paused = false;
+            });
+        }
+
+        /// Blocks the calling (reader) thread for as long as output is paused.
+        pub fn wait(&self) {
+            let (lock, condvar) = &*self.state;
+            if let Ok(state) = lock.lock() {
+                drop(condvar.wait_while(state, |state| state.paused));
+            }
+        }
+
+        #[cfg(test)]
+        pub fn is_paused(&self) -> bool {
+            self.state.0.lock().map(|state| state.paused).unwrap_or(false)
+        }
+
+        fn update(&self, change: impl FnOnce(&mut FlowState)) {
+            let (lock, condvar) = &*self.state;
+            if let Ok(mut state) = lock.lock() {
+                let paused = state.paused;
+                change(&mut state);
+                if state.paused != paused {
+                    condvar.notify_all();
+                }
+            }
+        }
+    }
+
+    /// Incremental UTF-8 decoder that carries incomplete sequences over to the next read.
+    #[derive(Clone, Debug, Default)]
+    pub struct Utf8Decoder {
+        pending: Vec<u8>,
+    }
+
+    impl Utf8Decoder {
+        pub fn new() -> Self {
+            Utf8Decoder::default()
+        }
+
+        pub fn decode(&mut self, bytes: &[u8]) -> String {
+            let mut input = std::mem::take(&mut self.pending);
+            input.extend_from_slice(bytes);
+
+            let mut decoded = String::with_capacity(input.len());
+            let mut rest = input.as_slice();
+            loop {
+                match str::from_utf8(rest) {
+                    Ok(valid) => {
+                        decoded.push_str(valid);
+                        break;
+                    }
+                    Err(error) => {
+                        let (valid, remainder) = rest.split_at(error.valid_up_to());
+                        decoded.push_str(str::from_utf8(valid).unwrap_or_default());
+                        match error.error_len() {
+                            Some(length) => {
+                                decoded.push(char::REPLACEMENT_CHARACTER);
+                                rest = &remainder[length..];
+                            }
+                            None => {
+                                // Truncated sequence at the end of the chunk, wait for the rest of it
+                                self.pending = remainder.to_vec();
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+            decoded
+        }
+
+        /// Flushes a dangling partial sequence, e.g. once the PTY has closed.
+        pub fn finish(&mut self) -> String {
+            if self.pending.is_empty() {
+                String::new()
+            } else {
+                self.pending.clear();
+                char::REPLACEMENT_CHARACTER.to_string()
+            }
+        }
+    }
+
+    #[derive(Clone, Debug, Default)]
+    pub struct OutputDecoder {
+        encoding: OutputEncoding,
+        utf8: Utf8Decoder,
+    }
+
+    impl OutputDecoder {
+        pub fn new(encoding: OutputEncoding) -> Self {
+            OutputDecoder {
+                encoding,
+                utf8: Utf8Decoder::new(),
+            }
+        }
+
+        pub fn encoding(&self) -> OutputEncoding {
+            self.encoding
+        }
+
+        pub fn decode(&mut self, bytes: &[u8]) -> String {
+            match self.encoding {
+                OutputEncoding::Utf8 => self.utf8.decode(bytes),
+                OutputEncoding::Base64 => STANDARD.encode(bytes),
+            }
+        }
+
+        pub fn finish(&mut self) -> String {
+            match self.encoding {
+                OutputEncoding::Utf8 => self.utf8.finish(),
+                OutputEncoding::Base64 => String::new(),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /// Decodes `bytes` split at every possible point, as if read in two chunks.
+        fn decode_split(bytes: &[u8]) -> Vec<String> {
+            (0..=bytes.len())
+                .map(|at| {
+                    let mut decoder = Utf8Decoder::new();
+                    let (first, second) = bytes.split_at(at);
+                    let mut decoded = decoder.decode(first);
+                    decoded.push_str(&decoder.decode(second));
+                    decoded.push_str(&decoder.finish());
+                    decoded
+                })
+                .collect()
+        }
+
+        #[test]
+        fn split_emoji() {
+            let text = "a😀b";
+            assert!(decode_split(text.as_bytes()).iter().all(|decoded| decoded == text));
+        }
+
+        #[test]
+        fn split_cjk() {
+            let text = "中文字";
+            assert!(decode_split(text.as_bytes()).iter().all(|decoded| decoded == text));
+        }
+
+        #[test]
+        fn one_byte_at_a_time() {
+            let text = "é中😀\u{301}";
+            let mut decoder = Utf8Decoder::new();
+            let decoded = text.as_bytes().iter().map(|byte| decoder.decode(&[*byte])).collect::<String>();
+            assert_eq!(decoded, text);
+            assert_eq!(decoder.finish(), "");
+        }
+
+        #[test]
+        fn invalid_bytes_are_replaced() {
+            let mut decoder = Utf8Decoder::new();
+            assert_eq!(decoder.decode(b"a\xffb"), "a\u{FFFD}b");
+            assert_eq!(decoder.decode(b"\x80"), "\u{FFFD}");
+
+            // An overlong encoding of '/' is two bad bytes, not one character
+            assert_eq!(decoder.decode(b"\xc0\xaf"), "\u{FFFD}\u{FFFD}");
+        }
+
+        #[test]
+        fn invalid_bytes_across_reads() {
+            let mut decoder = Utf8Decoder::new();
+
+            // The start of 中, then something that can't continue it
+            assert_eq!(decoder.decode(b"\xe4\xb8"), "");
+            assert_eq!(decoder.decode(b"a"), "\u{FFFD}a");
+
+            for decoded in decode_split(b"x\xe4\xb8\xe4\xb8\xadz") {
+                assert_eq!(decoded, "x\u{FFFD}中z");
+            }
+        }
+
+        #[test]
+        fn truncated_at_exit() {
+            let mut decoder = Utf8Decoder::new();
+            assert_eq!(decoder.decode(&"😀".as_bytes()[..3]), "");
+            assert_eq!(decoder.finish(), "\u{FFFD}");
+            assert_eq!(decoder.finish(), "");
+        }
+
+        #[test]
+        fn base64_keeps_raw_bytes() {
+            let bytes = b"\xe4\xb8\xad\xff\x00";
+            let mut decoder = OutputDecoder::new(OutputEncoding::Base64);
+            let encoded = [decoder.decode(&bytes[..1]), decoder.decode(&bytes[1..])];
+            let decoded = encoded
+                .iter()
+                .flat_map(|chunk| STANDARD.decode(chunk).unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(decoded, bytes);
+            assert_eq!(decoder.finish(), "");
+        }
+
+        #[test]
+        fn output_decoder_carries_utf8_over() {
+            let mut decoder = OutputDecoder::new(OutputEncoding::Utf8);
+            let bytes = "中".as_bytes();
+            assert_eq!(decoder.decode(&bytes[..1]), "");
+            assert_eq!(decoder.decode(&bytes[1..]), "中");
+        }
+    
+
+        #[test]
+        fn backpressure_pauses_between_watermarks() {
+            let backpressure = Backpressure::new(Some(FlowControl {
+                high_watermark: 100,
+                low_watermark: 10,
+            }));
+            backpressure.sent(60);
+            assert!(!backpressure.is_paused());
+            backpressure.sent(60);
+            assert!(backpressure.is_paused());
+            backpressure.ack(100);
+            assert!(backpressure.is_paused());
+            backpressure.ack(10);
+            assert!(!backpressure.is_paused());
+
+            backpressure.sent(200);
+            backpressure.reset();
+            assert!(!backpressure.is_paused());
+            backpressure.sent(99);
+            assert!(!backpressure.is_paused());
+        }
+
+        #[test]
+        fn backpressure_without_flow_control_never_pauses() {
+            let backpressure = Backpressure::new(None);
+            backpressure.sent(usize::MAX);
+            assert!(!backpressure.is_paused());
+        }
+
+        #[test]
+        fn flow_control_needs_ordered_watermarks() {
+            let flow = |high_watermark, low_watermark| FlowControl {
+                high_watermark,
+                low_watermark,
+            };
+            assert!(FlowControl::default().is_valid());
+            assert!(flow(10, 10).is_valid());
+            assert!(!flow(10, 20).is_valid());
+            assert!(!flow(10, 0).is_valid());
+            assert!(!flow(0, 0).is_valid());
+        }
+
+        #[test]
+        fn invalid_watermarks_are_rejected_when_parsed() {
+            let parse = |json: &str| serde_json::from_str::<FlowControl>(json);
+            assert_eq!(parse("{}").unwrap(), FlowControl::default());
+            let flow = parse(r#"{"high_watermark": 100, "low_watermark": 10}"#).unwrap();
+            assert_eq!(flow.high_watermark, 100);
+            assert!(parse(r#"{"high_watermark": 10, "low_watermark": 100}"#).is_err());
+            assert!(parse(r#"{"low_watermark": 0}"#).is_err());
+        }
+
+        #[test]
+        fn flush_interval_is_capped() {
+            let policy = FlushPolicy {
+                interval_ms: u64::MAX,
+                ..Default::default()
+            };
+            assert_eq!(policy.interval(), MAX_FLUSH_INTERVAL);
+            // Would panic if the deadline overflowed
+            let _ = std::time::Instant::now() + policy.interval();
+        }
+    }
+}
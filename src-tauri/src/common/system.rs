@@ -0,0 +1,69 @@
+pub mod shell {
+    use std::{env, fs, path::Path};
+
+    use portable_pty::CommandBuilder;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct ShellInfo {
+        pub path: String,
+        pub name: String,
+    }
+
+    impl ShellInfo {
+        pub fn resolve() -> Self {
+            let path = default_shell();
+            ShellInfo {
+                name: shell_name(&path),
+                path,
+            }
+        }
+    }
+
+    /// Resolves the user's login shell: `$SHELL` if it is executable, then the passwd entry,
+    /// then `/bin/sh` (`%ComSpec%` on Windows).
+    pub fn default_shell() -> String {
+        if let Ok(shell) = env::var("SHELL") {
+            if is_executable(&shell) {
+                return shell;
+            }
+        }
+
+        // portable_pty's default program already walks the passwd database and falls back to /bin/sh
+        CommandBuilder::new_default_prog().get_shell()
+    }
+
+    pub fn shell_name(path: &str) -> String {
+        Path::new(path)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.to_string())
+    }
+
+    /// The working directory of a running process, where the platform exposes it.
+    #[cfg(target_os = "linux")]
+    pub fn process_cwd(pid: u32) -> Option<String> {
+        fs::read_link(format!("/proc/{}/cwd", pid))
+            .ok()
+            .map(|path| path.to_string_lossy().to_string())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn process_cwd(_pid: u32) -> Option<String> {
+        None
+    }
+
+    #[cfg(unix)]
+    fn is_executable(path: &str) -> bool {
+        use std::os::unix::fs::PermissionsExt;
+
+        fs::metadata(path)
+            .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false)
+    }
+
+    #[cfg(not(unix))]
+    fn is_executable(path: &str) -> bool {
+        fs::metadata(path).map(|meta| meta.is_file()).unwrap_or(false)
+    }
+}
@@ -1,39 +1,69 @@
 pub mod term {
-    use core::str;
     use std::{
         collections::HashMap,
-        io::{BufRead, BufReader, Read, Write},
-        sync::{Arc, Mutex},
+        io::{self, ErrorKind, Read, Write},
+        ops::RangeInclusive,
+        path::Path,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex,
+        },
         thread::{self, spawn, JoinHandle},
+        time::{Duration, Instant},
     };
 
-    use crossbeam_channel::{unbounded, Receiver, Sender, TrySendError};
-    use portable_pty::{native_pty_system, Child, CommandBuilder, MasterPty, PtySize, SlavePty};
+    use crossbeam_channel::{at, bounded, never, select, unbounded, Receiver, Sender};
+    use portable_pty::{native_pty_system, Child, CommandBuilder, ExitStatus, MasterPty, PtySize};
     use serde::{Deserialize, Serialize};
-    use tauri::{AppHandle, Emitter};
+    use snafu::{ensure, IntoError, OptionExt, ResultExt};
+    use tauri::ipc::Channel;
     use uuid::Uuid;
 
-    use crate::common::app_state::BackendEvent;
+    use crate::common::{
+        app_state::{BackendEvent, FrontendEvent},
+        asciicast::{Cast, CastEvent, PlaybackStatus, Recorder, RecordingInfo},
+        command_history::{CommandHistory, CommandRecord},
+        config::ScrollbackConfig,
+        event_bus::EventBus,
+        error::{
+            InvalidFlowControlSnafu, InvalidIdleTimeLimitSnafu, InvalidSpeedSnafu, LockPoisonedSnafu,
+            NotPlaybackSnafu, PtyHandlesSnafu, PtyOpenSnafu, ResizeSnafu,
+            SpawnSnafu, TartError, TerminalClosedSnafu, UnknownTerminalSnafu, WriteFileSnafu,
+            WriteSnafu,
+        },
+        shell::{default_shell, process_cwd},
+        output::{Backpressure, FlowControl, FlushPolicy, OutputDecoder, OutputEncoding},
+        notifications::{AttentionReason, NotificationPolicy},
+        screen::{Alert, ShellMark, Snapshot, VirtualTerminal},
+        session_log::{LogTarget, SessionLog},
+        shell_integration,
+    };
 
     #[derive(Serialize, Deserialize, Clone, Debug)]
     #[serde(tag = "type")]
     pub enum TerminalCommand {
         Kill,
-        TerminalFailure {
-            id: Uuid,
-            reason: String,
-        },
         Write(String),
         Read(String),
-        Error {
-            scope: String,
-            reason: String,
-        },
 
         #[serde(with = "PtySizeDef")]
         Resize {
             size: PtySize,
         },
+        SetFlushPolicy {
+            policy: FlushPolicy,
+        },
+        Ack {
+            bytes: usize,
+        },
+        Pause,
+        Resume,
+        Seek {
+            position: f64,
+        },
+        SetSpeed {
+            speed: f64,
+        },
     }
 
     #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -49,13 +79,6 @@ pub mod term {
                 command,
             }
         }
-
-        pub fn result(&self, command: TerminalCommand) -> Self {
-            TerminalMessage {
-                id: self.id,
-                command,
-            }
-        }
     }
 
     #[derive(Serialize, Deserialize)]
@@ -67,219 +90,1233 @@ pub mod term {
         pixel_height: u16,
     }
 
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct ExitInfo {
+        pub code: u32,
+        pub signal: Option<String>,
+        pub success: bool,
+    }
+
+    impl From<ExitStatus> for ExitInfo {
+        fn from(status: ExitStatus) -> Self {
+            // portable_pty only exposes the signal name through Display
+            let signal = if status.success() {
+                None
+            } else {
+                status
+                    .to_string()
+                    .strip_prefix("Terminated by ")
+                    .map(|s| s.to_string())
+            };
+
+            ExitInfo {
+                code: status.exit_code(),
+                signal,
+                success: status.success(),
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    #[serde(tag = "type")]
+    pub enum TerminalState {
+        Running,
+        Exited { status: ExitInfo },
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum CloseOnExit {
+        Always,
+        #[default]
+        OnSuccess,
+        Never,
+    }
+
+    impl CloseOnExit {
+        pub fn should_close(&self, status: &ExitInfo) -> bool {
+            match self {
+                CloseOnExit::Always => true,
+                CloseOnExit::OnSuccess => status.success,
+                CloseOnExit::Never => false,
+            }
+        }
+    }
+
+    /// Environment every terminal starts with, before any user-supplied variables.
+    const DEFAULT_ENV: [(&str, &str); 4] = [
+        ("TERM", "xterm-256color"),
+        ("COLORTERM", "truecolor"),
+        ("TERM_PROGRAM", "tart"),
+        ("TERM_PROGRAM_VERSION", env!("CARGO_PKG_VERSION")),
+    ];
+
+    /// Everything the frontend may specify when creating a terminal.
+    #[derive(Serialize, Deserialize, Clone, Debug, Default)]
+    pub struct TerminalOptions {
+        /// Defaults to the user's shell when omitted
+        pub command: Option<String>,
+        pub args: Option<Vec<String>>,
+        pub login: Option<bool>,
+        pub title: Option<String>,
+        pub cwd: Option<String>,
+        pub env: Option<HashMap<String, String>>,
+        pub env_remove: Option<Vec<String>>,
+        pub clear_env: Option<bool>,
+        pub close_on_exit: Option<CloseOnExit>,
+        pub encoding: Option<OutputEncoding>,
+        pub flow_control: Option<FlowControl>,
+        pub flush_policy: Option<FlushPolicy>,
+        pub scrollback: Option<ScrollbackConfig>,
+
+        /// Load tart's OSC 133 scripts into bash, zsh or fish when launching them without arguments
+        pub shell_integration: Option<bool>,
+        pub notifications: Option<NotificationPolicy>,
+        pub log: Option<LogTarget>,
+
+        /// Name of the profile these options came from, if any
+        pub profile: Option<String>,
+
+        /// Set when the config fills in what the options left out
+        #[serde(skip)]
+        pub from_config: ConfigDefaults,
+    }
+
+    /// Which of a terminal's settings were taken from the config rather than set for it, and
+    /// so follow the config when it's reloaded.
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+    pub struct ConfigDefaults {
+        pub flush_policy: bool,
+        pub scrollback: bool,
+    }
+
+    impl TerminalOptions {
+        /// Overlays every option set in `overrides`. Environment variables are merged, and
+        /// overriding the command drops the original arguments.
+        pub fn merge(self, overrides: TerminalOptions) -> TerminalOptions {
+            let args = if overrides.command.is_some() {
+                overrides.args
+            } else {
+                overrides.args.or(self.args)
+            };
+            let env = match (self.env, overrides.env) {
+                (Some(mut env), Some(extra)) => {
+                    env.extend(extra);
+                    Some(env)
+                }
+                (env, extra) => extra.or(env),
+            };
+
+            TerminalOptions {
+                command: overrides.command.or(self.command),
+                args,
+                login: overrides.login.or(self.login),
+                title: overrides.title.or(self.title),
+                cwd: overrides.cwd.or(self.cwd),
+                env,
+                env_remove: overrides.env_remove.or(self.env_remove),
+                clear_env: overrides.clear_env.or(self.clear_env),
+                close_on_exit: overrides.close_on_exit.or(self.close_on_exit),
+                encoding: overrides.encoding.or(self.encoding),
+                flow_control: overrides.flow_control.or(self.flow_control),
+                flush_policy: overrides.flush_policy.or(self.flush_policy),
+                scrollback: overrides.scrollback.or(self.scrollback),
+                shell_integration: overrides.shell_integration.or(self.shell_integration),
+                notifications: overrides.notifications.or(self.notifications),
+                log: overrides.log.or(self.log),
+                profile: overrides.profile.or(self.profile),
+                from_config: self.from_config,
+            }
+        }
+    }
+
+    /// Where a terminal's output comes from.
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+    pub enum TerminalKind {
+        #[default]
+        Pty,
+
+        /// Read-only, replaying an asciicast file
+        Playback,
+    }
+
     #[derive(Serialize, Deserialize, Clone, Debug)]
     pub struct TerminalInfo {
         pub id: Uuid,
+
+        /// Terminals are listed (and their tabs shown) in the order they were created in
+        pub order: u64,
+        pub kind: TerminalKind,
         pub command: String,
         pub args: Option<Vec<String>>,
+        pub login: bool,
+
+        /// From the launch options, shown until the program or the user sets a title
         pub title: Option<String>,
 
+        /// Pinned by the user, takes precedence over whatever the program reports
+        pub user_title: Option<String>,
+
+        /// Last reported by the program through OSC 0/2
+        pub program_title: Option<String>,
+
+        /// The directory the terminal was launched in
+        pub cwd: Option<String>,
+
+        /// Where the shell is now, from OSC 7 or else the foreground process
+        pub current_dir: Option<String>,
+        pub env: HashMap<String, String>,
+        pub env_remove: Vec<String>,
+        pub clear_env: bool,
+        pub profile: Option<String>,
+        pub state: TerminalState,
+        pub close_on_exit: CloseOnExit,
+        pub encoding: OutputEncoding,
+        pub flush_policy: FlushPolicy,
+        pub flow_control: Option<FlowControl>,
+        pub scrollback: ScrollbackConfig,
+        pub shell_integration: bool,
+        pub notifications: NotificationPolicy,
+
+        #[serde(default)]
+        pub from_config: ConfigDefaults,
+
+        /// The tab the user has selected, which only has their attention while the window does
+        pub focused: bool,
+        pub recording: Option<RecordingInfo>,
+        pub playback: Option<PlaybackStatus>,
+        pub log: Option<LogTarget>,
+
         #[serde(with = "PtySizeDef")]
         pub size: PtySize,
     }
 
-    #[allow(dead_code)]
+    impl TerminalInfo {
+        pub fn display_title(&self) -> Option<String> {
+            self.user_title
+                .clone()
+                .or_else(|| self.program_title.clone())
+                .or_else(|| self.title.clone())
+        }
+
+        /// Options that start the same program over, in the directory the shell was last in.
+        pub fn options(&self) -> TerminalOptions {
+            TerminalOptions {
+                command: Some(self.command.clone()),
+                args: self.args.clone(),
+                login: Some(self.login),
+                title: self.title.clone(),
+                cwd: self.current_dir.clone().or_else(|| self.cwd.clone()),
+                env: Some(self.env.clone()),
+                env_remove: Some(self.env_remove.clone()),
+                clear_env: Some(self.clear_env),
+                close_on_exit: Some(self.close_on_exit),
+                encoding: Some(self.encoding),
+                flow_control: self.flow_control,
+                // Left to the config again, which may have changed since
+                flush_policy: (!self.from_config.flush_policy).then_some(self.flush_policy),
+                scrollback: (!self.from_config.scrollback).then(|| self.scrollback.clone()),
+                shell_integration: Some(self.shell_integration),
+                notifications: Some(self.notifications),
+                // Logs are named after the terminal's ID, so the new one gets a log of its own
+                log: None,
+                profile: self.profile.clone(),
+                from_config: ConfigDefaults::default(),
+            }
+        }
+    }
+
+    /// Hands out `TerminalInfo::order`.
+    static NEXT_ORDER: AtomicU64 = AtomicU64::new(0);
+
+    /// How long to keep draining output after the child exits before reporting it.
+    const EXIT_DRAIN_TIMEOUT: Duration = Duration::from_millis(100);
+
+    /// How often to look up the foreground process's directory for shells that don't report it.
+    const CWD_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// How often a playing recording reports its position.
+    const PLAYBACK_PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+    /// The playback speeds accepted, slower or faster than this is of no use to anyone watching.
+    const PLAYBACK_SPEEDS: RangeInclusive<f64> = 0.01..=100.0;
+
+    /// Chunks the reader may queue up before it blocks waiting for the terminal loop.
+    const READ_QUEUE_CAPACITY: usize = 64;
+
     pub struct TerminalContext {
-        pub handle: Box<dyn MasterPty>,
-        pub target: Box<dyn SlavePty>,
-        pub reader: BufReader<Box<dyn Read + Send>>,
+        pub handle: Box<dyn MasterPty + Send>,
+        pub reader: Box<dyn Read + Send>,
         pub writer: Box<dyn Write + Send>,
-        pub process: Box<dyn Child>,
+        pub process: Box<dyn Child + Send + Sync>,
     }
 
     #[derive(Clone, Debug)]
     pub enum ReadEvent {
-        Kill,
-        Data(String)
+        Data(Vec<u8>),
+        Closed,
+        Exited(ExitInfo),
+    }
+
+    #[cfg(unix)]
+    fn foreground_pid(handle: &dyn MasterPty) -> Option<u32> {
+        handle
+            .process_group_leader()
+            .and_then(|pid| u32::try_from(pid).ok())
+    }
+
+    #[cfg(not(unix))]
+    fn foreground_pid(_handle: &dyn MasterPty) -> Option<u32> {
+        None
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct Subscription {
+        pub subscription: Uuid,
+        pub snapshot: Snapshot,
+    }
+
+    /// Webview channels streaming a single terminal's events, keyed by subscription ID. Output
+    /// is acknowledged by one of them, so flow control counts each byte once however many
+    /// views there are, and not at all while there are none.
+    #[derive(Clone)]
+    pub struct Subscribers {
+        list: Arc<Mutex<SubscriberList>>,
+        backpressure: Backpressure,
+    }
+
+    #[derive(Default)]
+    struct SubscriberList {
+        channels: HashMap<Uuid, Channel<FrontendEvent>>,
+
+        /// The subscription whose output counts towards flow control
+        acker: Option<Uuid>,
+    }
+
+    impl SubscriberList {
+        /// Hands acknowledging over to another subscriber once the current one is gone. What
+        /// was sent to the old one will never be acknowledged, so the count starts over.
+        fn replace_acker(&mut self, backpressure: &Backpressure) {
+            if self.acker.is_some_and(|acker| self.channels.contains_key(&acker)) {
+                return;
+            }
+            let acker = self.channels.keys().next().copied();
+            if acker != self.acker {
+                self.acker = acker;
+                backpressure.reset();
+            }
+        }
+    }
+
+    impl Subscribers {
+        pub fn new(backpressure: Backpressure) -> Self {
+            Subscribers {
+                list: Arc::new(Mutex::new(SubscriberList::default())),
+                backpressure,
+            }
+        }
+
+        /// Adds a subscriber, which starts from a snapshot of the screen taken under the same lock.
+        pub fn add(&self, channel: Channel<FrontendEvent>) -> Uuid {
+            let id = Uuid::new_v4();
+            if let Ok(mut list) = self.list.lock() {
+                list.channels.insert(id, channel);
+                list.replace_acker(&self.backpressure);
+            }
+            id
+        }
+
+        pub fn remove(&self, id: Uuid) -> bool {
+            match self.list.lock() {
+                Ok(mut list) => {
+                    let removed = list.channels.remove(&id).is_some();
+                    list.replace_acker(&self.backpressure);
+                    removed
+                }
+                Err(_) => false,
+            }
+        }
+
+        /// Sends an event to every subscriber, dropping those whose webview has gone away.
+        pub fn send(&self, event: FrontendEvent) {
+            if let Ok(mut list) = self.list.lock() {
+                list.channels.retain(|_, channel| channel.send(event.clone()).is_ok());
+                list.replace_acker(&self.backpressure);
+            }
+        }
+
+        /// Sends output to every subscriber. Only the one acknowledging it is told how many
+        /// bytes it holds, and they only count as sent once it's been delivered to that one.
+        pub fn send_output(&self, id: Uuid, data: String, encoding: OutputEncoding, bytes: usize) {
+            let Ok(mut list) = self.list.lock() else {
+                return;
+            };
+            let acker = list.acker;
+            let mut delivered = false;
+            list.channels.retain(|subscription, channel| {
+                let acks = acker == Some(*subscription);
+                let sent = channel
+                    .send(FrontendEvent::TerminalRead {
+                        id,
+                        data: data.clone(),
+                        encoding,
+                        bytes: if acks { bytes } else { 0 },
+                    })
+                    .is_ok();
+                delivered |= acks && sent;
+                sent
+            });
+            if delivered {
+                self.backpressure.sent(bytes);
+            }
+            list.replace_acker(&self.backpressure);
+        }
+    }
+
+    impl std::fmt::Debug for Subscribers {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let count = self.list.lock().map(|list| list.channels.len()).unwrap_or(0);
+            f.debug_tuple("Subscribers").field(&count).finish()
+        }
     }
 
     #[derive(Clone, Debug)]
     pub struct Terminal {
-        _info: TerminalInfo,
+        _info: Arc<Mutex<TerminalInfo>>,
         pub commands: (Sender<TerminalMessage>, Receiver<TerminalMessage>),
-        pub results: (Sender<TerminalMessage>, Receiver<TerminalMessage>),
+        pub subscribers: Subscribers,
+        backpressure: Backpressure,
+        pub screen: Arc<Mutex<VirtualTerminal>>,
+        pub history: Arc<Mutex<CommandHistory>>,
+        recorder: Arc<Mutex<Option<Recorder>>>,
+        log: Arc<Mutex<Option<SessionLog>>>,
     }
 
     impl Terminal {
         pub fn new(
             commands: (Sender<TerminalMessage>, Receiver<TerminalMessage>),
-            results: (Sender<TerminalMessage>, Receiver<TerminalMessage>),
-            command: String,
-            args: Option<Vec<String>>,
-            title: Option<String>,
+            options: TerminalOptions,
         ) -> Self {
+            let size = PtySize {
+                rows: 24,
+                cols: 80,
+                pixel_width: 0,
+                pixel_height: 0,
+            };
+            let scrollback = options.scrollback.unwrap_or_default();
+            let backpressure = Backpressure::new(options.flow_control);
             Terminal {
-                _info: TerminalInfo {
+                _info: Arc::new(Mutex::new(TerminalInfo {
                     id: Uuid::new_v4(),
-                    command,
-                    args,
-                    title,
-                    size: PtySize {
-                        rows: 24,
-                        cols: 80,
-                        pixel_width: 0,
-                        pixel_height: 0,
-                    },
-                },
+                    order: NEXT_ORDER.fetch_add(1, Ordering::Relaxed),
+                    kind: TerminalKind::Pty,
+                    command: options.command.unwrap_or_else(default_shell),
+                    args: options.args,
+                    login: options.login.unwrap_or(false),
+                    title: options.title,
+                    user_title: None,
+                    program_title: None,
+                    cwd: options.cwd,
+                    current_dir: None,
+                    env: options.env.unwrap_or_default(),
+                    env_remove: options.env_remove.unwrap_or_default(),
+                    clear_env: options.clear_env.unwrap_or(false),
+                    profile: options.profile,
+                    state: TerminalState::Running,
+                    close_on_exit: options.close_on_exit.unwrap_or_default(),
+                    encoding: options.encoding.unwrap_or_default(),
+                    flush_policy: options.flush_policy.unwrap_or_default(),
+                    flow_control: options.flow_control,
+                    scrollback: scrollback.clone(),
+                    shell_integration: options.shell_integration.unwrap_or(false),
+                    notifications: options.notifications.unwrap_or_default(),
+                    from_config: options.from_config,
+                    focused: false,
+                    recording: None,
+                    playback: None,
+                    log: None,
+                    size,
+                })),
                 commands,
-                results,
+                subscribers: Subscribers::new(backpressure.clone()),
+                backpressure,
+                screen: Arc::new(Mutex::new(VirtualTerminal::new(size, scrollback))),
+                history: Arc::new(Mutex::new(CommandHistory::default())),
+                recorder: Arc::new(Mutex::new(None)),
+                log: Arc::new(Mutex::new(None)),
             }
         }
 
         pub fn id(&self) -> Uuid {
-            self._info.id
+            self.info().id
         }
 
         pub fn info(&self) -> TerminalInfo {
-            self._info.clone()
+            self._info
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .clone()
         }
 
-        pub fn send_event(
-            &self,
-            command: TerminalCommand,
-        ) -> Result<(), TrySendError<TerminalMessage>> {
-            self.results.0.try_send(TerminalMessage::new(command))
+        fn set_state(&self, state: TerminalState) {
+            if let Ok(mut info) = self._info.lock() {
+                info.state = state;
+            }
         }
 
-        pub fn send_result(
+        /// Output only goes to this terminal's subscribers, never to the global event bus.
+        fn emit_read(&self, encoding: OutputEncoding, data: String, bytes: usize) {
+            if !data.is_empty() {
+                self.subscribers.send_output(self.id(), data, encoding, bytes);
+            }
+        }
+
+        /// Emits the buffered output. The screen is updated under the same lock, so a snapshot
+        /// followed by the events sent after it neither repeats nor misses any output.
+        fn flush_output(
             &self,
-            message: TerminalMessage,
-            command: TerminalCommand,
-        ) -> Result<(), TrySendError<TerminalMessage>> {
-            self.results.0.try_send(message.result(command))
+            bus: &EventBus,
+            decoder: &mut OutputDecoder,
+            pending: &mut Vec<u8>,
+        ) {
+            let bytes = pending.len();
+            if bytes > 0 {
+                let mut screen = self.screen.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                screen.feed(pending);
+                self.emit_read(decoder.encoding(), decoder.decode(pending), bytes);
+                pending.clear();
+                self.update_program_title(bus, screen.screen().title());
+                if let Some(dir) = screen.screen().working_directory() {
+                    self.update_current_dir(bus, dir);
+                }
+                let marks = screen.take_marks();
+                let alerts = screen.take_alerts();
+                drop(screen);
+                self.update_history(bus, marks);
+                self.raise_alerts(bus, alerts);
+            }
+        }
+
+        fn update_history(&self, bus: &EventBus, marks: Vec<ShellMark>) {
+            if marks.is_empty() {
+                return;
+            }
+            let finished = {
+                let mut history = self.history.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                marks.into_iter().filter_map(|mark| history.apply(mark)).collect::<Vec<_>>()
+            };
+            let info = self.info();
+            for record in finished {
+                let duration = record.duration().unwrap_or_default();
+                let command = record.command.unwrap_or_default();
+                bus.emit(FrontendEvent::CommandFinished {
+                    id: info.id,
+                    command: command.clone(),
+                    exit_code: record.exit_code,
+                    duration,
+                });
+                if info.notifications.should_notify(duration, record.exit_code) {
+                    let reason = AttentionReason::CommandFinished {
+                        command,
+                        exit_code: record.exit_code,
+                        duration,
+                    };
+                    bus.request_attention(&info, reason);
+                }
+            }
+        }
+
+        fn raise_alerts(&self, bus: &EventBus, alerts: Vec<Alert>) {
+            if alerts.is_empty() {
+                return;
+            }
+            let info = self.info();
+            let mut rang = false;
+            for alert in alerts {
+                match alert {
+                    // A burst of bells (e.g. failed completions) only needs to be reported once
+                    Alert::Bell if rang => (),
+                    Alert::Bell => {
+                        rang = true;
+                        bus.request_attention(&info, AttentionReason::Bell);
+                    }
+                    Alert::Notification { title, body } => {
+                        bus.request_attention(&info, AttentionReason::Notification { title, body });
+                    }
+                }
+            }
         }
 
-        pub fn recv_command(&self) -> Option<TerminalMessage> {
-            if let Ok(recv) = self.commands.1.try_recv() {
-                Some(recv)
+        pub fn command_history(&self) -> Vec<CommandRecord> {
+            self.history
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .list()
+        }
+
+        fn update_current_dir(&self, bus: &EventBus, dir: &str) {
+            match self._info.lock() {
+                Ok(mut info) if info.current_dir.as_deref() != Some(dir) => {
+                    info.current_dir = Some(dir.to_string());
+                }
+                _ => return,
+            }
+            bus.emit(FrontendEvent::TerminalCwdChanged {
+                id: self.id(),
+                cwd: dir.to_string(),
+            });
+        }
+
+        /// Falls back on the foreground process's directory unless the shell reports its own.
+        fn poll_current_dir(&self, bus: &EventBus, handle: &dyn MasterPty, child: Option<u32>) {
+            let reported = self
+                .screen
+                .lock()
+                .map(|screen| screen.screen().working_directory().is_some())
+                .unwrap_or(false);
+            if reported {
+                return;
+            }
+            if let Some(dir) = foreground_pid(handle).or(child).and_then(process_cwd) {
+                self.update_current_dir(bus, &dir);
+            }
+        }
+
+        fn update_program_title(&self, bus: &EventBus, title: Option<&str>) {
+            let info = match self._info.lock() {
+                Ok(mut info) if info.program_title.as_deref() != title => {
+                    info.program_title = title.map(str::to_string);
+                    info.clone()
+                }
+                _ => return,
+            };
+            bus.emit(FrontendEvent::TerminalTitleChanged {
+                id: info.id,
+                title: info.display_title(),
+            });
+        }
+
+        /// Pins a title over the program's, or goes back to the program's with `None`.
+        pub fn set_user_title(&self, title: Option<String>) -> TerminalInfo {
+            let mut info = self._info.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            info.user_title = title;
+            info.clone()
+        }
+
+        pub fn set_notifications(&self, policy: NotificationPolicy) -> TerminalInfo {
+            let mut info = self._info.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            info.notifications = policy;
+            info.clone()
+        }
+
+        /// Applies a new scrollback limit to the screen, dropping the oldest lines if they no longer fit.
+        pub fn set_scrollback(&self, limit: ScrollbackConfig) {
+            if let Ok(mut info) = self._info.lock() {
+                info.scrollback = limit.clone();
+            }
+            self.screen
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .set_scrollback(limit);
+        }
+
+        /// Starts writing the session to an asciicast file, replacing any recording in progress.
+        pub fn start_recording(&self, path: &Path, include_input: bool) -> Result<RecordingInfo, TartError> {
+            let info = self.info();
+            let env = [("SHELL", info.command.as_str()), DEFAULT_ENV[0]];
+            let recorder = Recorder::create(path, info.size, include_input, info.display_title(), &env)?;
+            let started = recorder.info().clone();
+
+            let mut recording = self.recorder.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if let Some(previous) = recording.replace(recorder) {
+                let _ = previous.finish();
+            }
+            if let Ok(mut info) = self._info.lock() {
+                info.recording = Some(started.clone());
+            }
+            Ok(started)
+        }
+
+        pub fn stop_recording(&self) -> Result<Option<RecordingInfo>, TartError> {
+            let recorder = self
+                .recorder
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .take();
+            if let Ok(mut info) = self._info.lock() {
+                info.recording = None;
+            }
+            match recorder {
+                Some(recorder) => {
+                    let path = recorder.info().path.clone();
+                    recorder.finish().context(WriteFileSnafu { path }).map(Some)
+                }
+                None => Ok(None),
+            }
+        }
+
+        /// Passes an event to the recording, if there is one. A recording that can't be
+        /// written to anymore is dropped rather than failing the terminal.
+        fn record(&self, event: impl FnOnce(&mut Recorder) -> io::Result<()>) {
+            let mut recording = self.recorder.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let Some(recorder) = recording.as_mut() else {
+                return;
+            };
+            if let Err(e) = event(recorder) {
+                let error = WriteFileSnafu {
+                    path: recorder.info().path.clone(),
+                }
+                .into_error(e);
+                println!("Stopped recording: {}", error);
+                *recording = None;
+                if let Ok(mut info) = self._info.lock() {
+                    info.recording = None;
+                }
+            }
+        }
+
+        /// Starts logging output to `target`, or stops with `None`. Any previous log is closed.
+        pub fn set_logging(&self, target: Option<&LogTarget>) -> Result<Option<LogTarget>, TartError> {
+            let log = target.map(|target| SessionLog::open(target, self.id())).transpose()?;
+            let started = log.as_ref().map(|log| log.target().clone());
+
+            let mut current = self.log.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if let Some(previous) = std::mem::replace(&mut *current, log) {
+                let _ = previous.finish();
+            }
+            if let Ok(mut info) = self._info.lock() {
+                info.log = started.clone();
+            }
+            Ok(started)
+        }
+
+        /// Appends output to the log, if there is one. Like a recording, a log that can't be
+        /// written to anymore is dropped rather than failing the terminal.
+        fn log_output(&self, data: &[u8]) {
+            let mut current = self.log.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let Some(log) = current.as_mut() else {
+                return;
+            };
+            if let Err(e) = log.write(data) {
+                let error = WriteFileSnafu {
+                    path: log.target().path.clone(),
+                }
+                .into_error(e);
+                println!("Stopped logging: {}", error);
+                *current = None;
+                if let Ok(mut info) = self._info.lock() {
+                    info.log = None;
+                }
+            }
+        }
+
+        fn set_focused(&self, focused: bool) {
+            if let Ok(mut info) = self._info.lock() {
+                info.focused = focused;
+            }
+        }
+
+        pub fn snapshot(&self) -> Snapshot {
+            self.screen
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .screen()
+                .snapshot()
+        }
+
+        /// Adds a subscriber along with the snapshot it should start from.
+        pub fn subscribe(&self, channel: Channel<FrontendEvent>) -> Subscription {
+            let screen = self.screen.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            Subscription {
+                subscription: self.subscribers.add(channel),
+                snapshot: screen.screen().snapshot(),
+            }
+        }
+
+        fn command_builder(info: TerminalInfo) -> CommandBuilder {
+            let args = info.args.unwrap_or_default();
+
+            // Only a plain interactive shell can be taught to load the scripts
+            let injection = if info.shell_integration && args.is_empty() && cfg!(unix) {
+                shell_integration::inject(&info.command, info.login, &info.env).unwrap_or_else(|e| {
+                    println!("Failed to set up shell integration: {}", e);
+                    None
+                })
             } else {
                 None
+            };
+
+            // portable_pty runs argv[0] itself, so a login shell can't get the conventional
+            // `-name` and is asked with `-l` instead
+            let mut cmd = if let Some(injection) = &injection {
+                let mut cmd = CommandBuilder::new(&info.command);
+                cmd.args(&injection.args);
+                cmd
+            } else {
+                let mut cmd = CommandBuilder::new(&info.command);
+                if info.login && cfg!(unix) {
+                    cmd.arg("-l");
+                }
+                cmd.args(args);
+                cmd
+            };
+
+            if let Some(cwd) = info.cwd {
+                cmd.cwd(cwd);
+            }
+            if info.clear_env {
+                cmd.env_clear();
+            }
+            for (key, value) in DEFAULT_ENV {
+                cmd.env(key, value);
+            }
+            for key in info.env_remove {
+                cmd.env_remove(key);
+            }
+            for (key, value) in info.env {
+                cmd.env(key, value);
+            }
+            for (key, value) in injection.map(|injection| injection.env).unwrap_or_default() {
+                cmd.env(key, value);
+            }
+            cmd
+        }
+
+        /// Fails the way spawning would for a path that can't be run, but keeps the reason's
+        /// error kind, which portable_pty only reports as a message. Commands looked up in
+        /// `PATH` are left to it.
+        #[cfg(unix)]
+        fn check_executable(command: &str) -> io::Result<()> {
+            use std::os::unix::fs::PermissionsExt;
+
+            let path = Path::new(command);
+            if !path.is_absolute() {
+                return Ok(());
+            }
+            let metadata = path.metadata()?;
+            if metadata.is_dir() || metadata.permissions().mode() & 0o111 == 0 {
+                return Err(io::Error::new(ErrorKind::PermissionDenied, "not an executable file"));
             }
+            Ok(())
         }
 
-        fn get_pty(&self) -> Result<TerminalContext, String> {
+        #[cfg(not(unix))]
+        fn check_executable(_command: &str) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn get_pty(&self) -> Result<TerminalContext, TartError> {
             let pty_system = native_pty_system();
-            let info = self._info.clone();
-            if let Ok(pair) = pty_system.openpty(info.size) {
-                let mut cmd = CommandBuilder::new(info.command);
-                if let Some(args) = info.args {
-                    cmd.args(args);
-                }
-                if let Ok(child) = pair.slave.spawn_command(cmd) {
-                    let reader = pair.master.try_clone_reader();
-                    let writer = pair.master.take_writer();
-                    if reader.is_ok() && writer.is_ok() {
-                        Ok(TerminalContext {
-                            handle: pair.master,
-                            target: pair.slave,
-                            reader: BufReader::new(reader.unwrap()),
-                            writer: writer.unwrap(),
-                            process: child,
-                        })
-                    } else {
-                        Err("Failed to get IO handles".to_string())
+            let info = self.info();
+            let pair = pty_system
+                .openpty(info.size)
+                .map_err(|e| PtyOpenSnafu { reason: e.to_string() }.build())?;
+
+            let command = info.command.clone();
+            Terminal::check_executable(&command).context(SpawnSnafu { command: &command })?;
+            let child = pair
+                .slave
+                .spawn_command(Terminal::command_builder(info))
+                .map_err(|e| {
+                    // Keep the underlying IO error (e.g. NotFound) when portable_pty has one
+                    e.downcast::<io::Error>()
+                        .unwrap_or_else(|e| io::Error::other(e.to_string()))
+                })
+                .context(SpawnSnafu { command })?;
+
+            // Only the child may hold the slave side open, otherwise reads never see EOF
+            drop(pair.slave);
+            let reader = pair
+                .master
+                .try_clone_reader()
+                .map_err(|e| PtyHandlesSnafu { reason: e.to_string() }.build())?;
+            let writer = pair
+                .master
+                .take_writer()
+                .map_err(|e| PtyHandlesSnafu { reason: e.to_string() }.build())?;
+
+            Ok(TerminalContext {
+                handle: pair.master,
+                reader,
+                writer,
+                process: child,
+            })
+        }
+
+        pub fn run_loop(&self, bus: EventBus, mut context: TerminalContext) -> () {
+            let child_pid = context.process.process_id();
+            let mut cwd_poll_at = Instant::now();
+            let (tx, rx) = bounded::<ReadEvent>(READ_QUEUE_CAPACITY);
+            let backpressure = self.backpressure.clone();
+            let mut decoder = OutputDecoder::new(self.info().encoding);
+            let mut policy = self.info().flush_policy;
+            let mut pending: Vec<u8> = Vec::new();
+            let mut flush_at: Option<Instant> = None;
+            let mut killer = context.process.clone_killer();
+
+            let reader_tx = tx.clone();
+            let mut reader = context.reader;
+            let gate = backpressure.clone();
+            spawn(move || {
+                let mut buffer = [0u8; 8192];
+                loop {
+                    // While paused the child blocks on a full PTY buffer instead of us buffering for it
+                    gate.wait();
+                    match reader.read(&mut buffer) {
+                        Ok(0) => break,
+                        Ok(count) => {
+                            if reader_tx
+                                .send(ReadEvent::Data(buffer[..count].to_vec()))
+                                .is_err()
+                            {
+                                return;
+                            }
+                        }
+                        Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                        Err(_) => break,
                     }
-                } else {
-                    Err("Failed to spawn command".to_string())
                 }
-            } else {
-                Err("PTY open failed".to_string())
-            }
-        }
-
-        pub fn run_loop(&self, app: AppHandle) -> () {
-            match self.get_pty() {
-                Ok(mut context) => {
-                    let (tx, rx) = crossbeam_channel::unbounded::<ReadEvent>();
-                    let tx_inner = tx.clone();
-                    let rx_inner = rx.clone();
-                    let mut reader = context.reader;
-                    spawn(move || {
-                        loop {
-                            if let Ok(e) = rx_inner.try_recv() {
-                                if let ReadEvent::Kill = e {
-                                    break;
+                let _ = reader_tx.send(ReadEvent::Closed);
+            });
+
+            let mut process = context.process;
+            spawn(move || {
+                if let Ok(status) = process.wait() {
+                    let _ = tx.send(ReadEvent::Exited(status.into()));
+                }
+            });
+
+            loop {
+                let flush_timer = match flush_at {
+                    Some(deadline) => at(deadline),
+                    None => never(),
+                };
+
+                // A timer of its own, so an idle shell's `cd` still shows up
+                let cwd_timer = at(cwd_poll_at);
+
+                select! {
+                    recv(self.commands.1) -> cmd => match cmd {
+                        Ok(cmd) => match cmd.command {
+                            TerminalCommand::Kill => {
+                                let _ = killer.kill();
+                                break;
+                            }
+                            TerminalCommand::Write(data) => {
+                                self.record(|recorder| recorder.input(&data));
+                                if let Err(e) = context.writer.write_all(data.as_bytes()) {
+                                    let error = WriteSnafu { id: self.id() }.into_error(e);
+                                    println!("{}", error);
+                                    bus.emit(FrontendEvent::TerminalError {
+                                        id: self.id(),
+                                        error: error.payload(),
+                                    });
                                 }
                             }
-
-                            let data = {
-                                if let Ok(data) = reader.fill_buf() {
-                                    if data.len() > 0 {
-                                        if let Ok(parsed) = str::from_utf8(data) {
-                                            Some(parsed.to_string())
-                                        } else {
-                                            None
-                                        }
-                                    } else {
-                                        None
+                            TerminalCommand::Resize { size } => {
+                                if let Err(e) = context.handle.resize(size) {
+                                    let error = ResizeSnafu {
+                                        id: self.id(),
+                                        reason: e.to_string(),
                                     }
+                                    .build();
+                                    println!("{}", error);
+                                    bus.emit(FrontendEvent::TerminalError {
+                                        id: self.id(),
+                                        error: error.payload(),
+                                    });
                                 } else {
-                                    None
+                                    if let Ok(mut info) = self._info.lock() {
+                                        info.size = size;
+                                    }
+                                    self.record(|recorder| recorder.resize(size));
+                                    let mut screen = self
+                                        .screen
+                                        .lock()
+                                        .unwrap_or_else(|poisoned| poisoned.into_inner());
+                                    screen.resize(size);
+                                    self.subscribers
+                                        .send(FrontendEvent::TerminalResized { id: self.id(), size });
                                 }
-                            };
-
-                            if let Some(d) = data {
-                                reader.consume(d.len());
-                                let _ = tx_inner.send(ReadEvent::Data(d));
                             }
+                            TerminalCommand::Ack { bytes } => backpressure.ack(bytes),
+                            TerminalCommand::SetFlushPolicy { policy: updated } => {
+                                policy = updated;
+                                if let Ok(mut info) = self._info.lock() {
+                                    info.flush_policy = updated;
+                                }
+                            }
+                            _ => (),
+                        },
+                        Err(_) => {
+                            let _ = killer.kill();
+                            break;
+                        }
+                    },
+                    recv(rx) -> evt => match evt {
+                        Ok(ReadEvent::Data(data)) => {
+                            self.record(|recorder| recorder.output(&data));
+                            self.log_output(&data);
+                            pending.extend_from_slice(&data);
+                            if policy.interval_ms == 0 || pending.len() >= policy.max_bytes {
+                                self.flush_output(&bus, &mut decoder, &mut pending);
+                                flush_at = None;
+                            } else if flush_at.is_none() {
+                                flush_at = Some(Instant::now() + policy.interval());
+                            }
+                        }
+                        Ok(ReadEvent::Closed) => (),
+                        Ok(ReadEvent::Exited(status)) => {
+                            // The child can exit before the reader has drained the PTY, so
+                            // give it a moment to reach EOF without waiting on orphaned
+                            // grandchildren that keep the PTY open.
+                            let deadline = Instant::now() + EXIT_DRAIN_TIMEOUT;
+                            while let Ok(ReadEvent::Data(data)) = rx.recv_deadline(deadline) {
+                                self.record(|recorder| recorder.output(&data));
+                                self.log_output(&data);
+                                pending.extend_from_slice(&data);
+                            }
+                            self.flush_output(&bus, &mut decoder, &mut pending);
+                            self.emit_read(decoder.encoding(), decoder.finish(), 0);
+
+                            self.set_state(TerminalState::Exited {
+                                status: status.clone(),
+                            });
+                            self.subscribers.send(FrontendEvent::TerminalExited {
+                                id: self.id(),
+                                status: status.clone(),
+                            });
+                            bus.emit_backend(BackendEvent::TerminalExited {
+                                id: self.id(),
+                                status,
+                            });
+                            break;
                         }
-                    });
-                    loop {
-                        if let Some(cmd) = self.recv_command() {
-                            match cmd.clone().command {
-                                TerminalCommand::Kill => {
-                                    let _ = context.process.kill();
-                                    break;
+                        Err(_) => break,
+                    },
+                    recv(flush_timer) -> _ => {
+                        self.flush_output(&bus, &mut decoder, &mut pending);
+                        flush_at = None;
+                    }
+                    recv(cwd_timer) -> _ => {
+                        cwd_poll_at = Instant::now() + CWD_POLL_INTERVAL;
+                        self.poll_current_dir(&bus, &*context.handle, child_pid);
+                    }
+                }
+            }
+            // Don't leave the reader parked on the gate once nobody will ack anymore
+            backpressure.release();
+            if let Err(e) = self.stop_recording() {
+                println!("{}", e);
+            }
+            let _ = self.set_logging(None);
+        }
+
+        /// Sizes a freshly created terminal like the one it replaces and puts that one's
+        /// output back on screen, above whatever the new program prints.
+        fn prepare_restore(&self, size: PtySize, contents: Option<&str>) {
+            if let Ok(mut info) = self._info.lock() {
+                info.size = size;
+            }
+            let mut screen = self.screen.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            screen.resize(size);
+            if let Some(contents) = contents.filter(|contents| !contents.is_empty()) {
+                screen.feed(contents.as_bytes());
+                screen.feed(b"\r\n");
+            }
+        }
+
+        /// The last `lines` lines of output, see `Screen::tail`.
+        pub fn tail(&self, lines: usize) -> String {
+            self.screen
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .screen()
+                .tail(lines)
+        }
+
+        /// Turns a freshly created terminal into a read-only player for `cast`.
+        fn prepare_playback(&self, cast: &Cast, speed: f64) {
+            if let Ok(mut info) = self._info.lock() {
+                info.kind = TerminalKind::Playback;
+                info.size = cast.size;
+                info.playback = Some(PlaybackStatus {
+                    position: 0.0,
+                    duration: cast.duration(),
+                    speed,
+                    paused: false,
+                });
+            }
+            self.screen
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .resize(cast.size);
+        }
+
+        fn report_playback(&self, bus: &EventBus, status: PlaybackStatus) {
+            let id = match self._info.lock() {
+                Ok(mut info) => {
+                    info.playback = Some(status);
+                    info.id
+                }
+                Err(_) => return,
+            };
+            bus.emit(FrontendEvent::PlaybackProgress { id, status });
+        }
+
+        /// Rebuilds the screen as it was `position` seconds in and has subscribers start over
+        /// from there. Returns the index of the first event still to be played.
+        fn seek_playback(&self, cast: &Cast, position: f64) -> usize {
+            let mut screen = self.screen.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let mut size = cast.size;
+            screen.clear(size);
+
+            let mut next = 0;
+            while let Some((time, event)) = cast.events.get(next) {
+                if *time > position {
+                    break;
+                }
+                match event {
+                    CastEvent::Output(data) => screen.feed(data.as_bytes()),
+                    CastEvent::Resize(resized) => {
+                        size = *resized;
+                        screen.resize(size);
+                    }
+                }
+                next += 1;
+            }
+            screen.take_marks();
+            screen.take_alerts();
+
+            if let Ok(mut info) = self._info.lock() {
+                info.size = size;
+            }
+            let id = self.id();
+            let mut decoder = OutputDecoder::new(self.info().encoding);
+            // RIS first, so the view drops what it had along with its scrollback
+            let replay = format!("\x1bc{}", screen.screen().snapshot().data);
+            let data = decoder.decode(replay.as_bytes()) + &decoder.finish();
+            self.subscribers.send(FrontendEvent::TerminalResized { id, size });
+            self.emit_read(decoder.encoding(), data, 0);
+            next
+        }
+
+        /// Plays `cast` back in place of a PTY until the terminal is removed. Playback can be
+        /// paused, sped up and moved around, writes and resizes are ignored.
+        pub fn run_playback(&self, bus: EventBus, cast: Cast, speed: f64) {
+            let mut decoder = OutputDecoder::new(self.info().encoding);
+            let duration = cast.duration();
+            let mut status = PlaybackStatus {
+                position: 0.0,
+                duration,
+                speed,
+                paused: false,
+            };
+            // `status.position` is where playback was at `resumed`
+            let mut resumed = Instant::now();
+            let mut next = 0;
+            let mut progress_at = Instant::now();
+
+            let position = |status: &PlaybackStatus, resumed: Instant| {
+                if status.paused {
+                    status.position
+                } else {
+                    (status.position + resumed.elapsed().as_secs_f64() * status.speed).min(duration)
+                }
+            };
+
+            loop {
+                let event_timer = match cast.events.get(next) {
+                    Some((time, _)) if !status.paused => {
+                        // An event too far off to represent just never comes, rather than panicking
+                        let wait = ((time - status.position) / status.speed).max(0.0);
+                        match Duration::try_from_secs_f64(wait).ok().and_then(|wait| resumed.checked_add(wait)) {
+                            Some(due) => at(due),
+                            None => never(),
+                        }
+                    }
+                    _ => never(),
+                };
+                let progress_timer = if status.paused { never() } else { at(progress_at) };
+
+                select! {
+                    recv(self.commands.1) -> cmd => match cmd {
+                        Ok(cmd) => {
+                            match cmd.command {
+                                TerminalCommand::Kill => break,
+                                TerminalCommand::Pause => {
+                                    status.position = position(&status, resumed);
+                                    status.paused = true;
                                 }
-                                TerminalCommand::Write(data) => {
-                                    println!("WRITING {:?}", data);
-                                    if let Err(e) = context.writer.write_all(data.as_bytes()) {
-                                        println!("{:?}", e);
-                                        let _ = self.send_result(
-                                            cmd.clone(),
-                                            TerminalCommand::Error {
-                                                scope: "pty".to_string(),
-                                                reason: "write_failure".to_string(),
-                                            },
-                                        );
+                                TerminalCommand::Resume => {
+                                    // Resuming at the end plays it again
+                                    if status.position >= duration {
+                                        next = self.seek_playback(&cast, 0.0);
+                                        status.position = 0.0;
                                     }
+                                    status.paused = false;
+                                    resumed = Instant::now();
                                 }
-                                TerminalCommand::Resize { size } => {
-                                    if let Err(_) = context.handle.resize(size) {
-                                        let _ = self.send_result(
-                                            cmd.clone(),
-                                            TerminalCommand::Error {
-                                                scope: "pty".to_string(),
-                                                reason: "resize_failure".to_string(),
-                                            },
-                                        );
+                                TerminalCommand::Seek { position } => {
+                                    // Not clamp, which panics if the duration ever came out negative
+                                    let position = position.max(0.0).min(duration);
+                                    next = self.seek_playback(&cast, position);
+                                    status.position = position;
+                                    resumed = Instant::now();
+                                }
+                                TerminalCommand::SetSpeed { speed } => {
+                                    status.position = position(&status, resumed);
+                                    status.speed = speed;
+                                    resumed = Instant::now();
+                                }
+                                _ => continue,
+                            }
+                            self.report_playback(&bus, status);
+                        }
+                        Err(_) => break,
+                    },
+                    recv(event_timer) -> _ => {
+                        let now = position(&status, resumed);
+                        let mut pending = Vec::new();
+                        while let Some((time, event)) = cast.events.get(next) {
+                            if *time > now {
+                                break;
+                            }
+                            match event {
+                                CastEvent::Output(data) => pending.extend_from_slice(data.as_bytes()),
+                                CastEvent::Resize(size) => {
+                                    self.flush_output(&bus, &mut decoder, &mut pending);
+                                    if let Ok(mut info) = self._info.lock() {
+                                        info.size = *size;
                                     }
+                                    self.screen
+                                        .lock()
+                                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                                        .resize(*size);
+                                    self.subscribers.send(FrontendEvent::TerminalResized { id: self.id(), size: *size });
                                 }
-                                _ => (),
                             }
+                            next += 1;
                         }
+                        self.flush_output(&bus, &mut decoder, &mut pending);
 
-                        if let Ok(evt) = rx.try_recv() {
-                            if let ReadEvent::Data(data) = evt {
-                                let _ = app.emit(
-                                    "tart://internal",
-                                    BackendEvent::TerminalRead {
-                                        id: self.id(),
-                                        data,
-                                    },
-                                );
-                            }
+                        if next == cast.events.len() {
+                            status.position = duration;
+                            status.paused = true;
+                            self.report_playback(&bus, status);
                         }
                     }
-                    let _ = tx.try_send(ReadEvent::Kill);
-                }
-                Err(reason) => {
-                    let _ = self.send_event(TerminalCommand::TerminalFailure {
-                        id: self.id(),
-                        reason,
-                    });
+                    recv(progress_timer) -> _ => {
+                        progress_at = Instant::now() + PLAYBACK_PROGRESS_INTERVAL;
+                        self.report_playback(
+                            &bus,
+                            PlaybackStatus {
+                                position: position(&status, resumed),
+                                ..status
+                            },
+                        );
+                    }
                 }
-            };
+            }
         }
     }
 
@@ -299,46 +1336,263 @@ pub mod term {
 
         pub fn create_terminal(
             &mut self,
-            app: AppHandle,
-            command: String,
-            args: Option<Vec<String>>,
-            title: Option<String>,
-        ) -> Result<Terminal, &str> {
+            bus: EventBus,
+            options: TerminalOptions,
+        ) -> Result<Terminal, TartError> {
             let commands = unbounded::<TerminalMessage>();
-            let results = unbounded::<TerminalMessage>();
             let term = Terminal::new(
                 (commands.0.clone(), commands.1.clone()),
-                (results.0.clone(), results.1.clone()),
-                command,
-                args,
-                title,
+                options.clone(),
             );
-            if let Ok(mut terminals) = self.terminals.lock() {
-                if let Ok(mut threads) = self.threads.lock() {
-                    let cloned = term.clone();
-                    terminals.insert(term.clone().id(), term.clone());
-                    threads.insert(
-                        term.clone().id(),
-                        thread::spawn(move || term.run_loop(app.clone())),
-                    );
-                    return Ok(cloned);
+            self.start_terminal(bus, term, options.log.as_ref())
+        }
+
+        /// Recreates a terminal from a saved session: started with `options`, at the size it
+        /// had, and with the output it had then already on screen.
+        pub fn restore_terminal(
+            &mut self,
+            bus: EventBus,
+            options: TerminalOptions,
+            size: PtySize,
+            contents: Option<&str>,
+        ) -> Result<Terminal, TartError> {
+            let commands = unbounded::<TerminalMessage>();
+            let term = Terminal::new(
+                (commands.0.clone(), commands.1.clone()),
+                options.clone(),
+            );
+            term.prepare_restore(size, contents);
+            self.start_terminal(bus, term, options.log.as_ref())
+        }
+
+        fn start_terminal(
+            &mut self,
+            bus: EventBus,
+            term: Terminal,
+            log: Option<&LogTarget>,
+        ) -> Result<Terminal, TartError> {
+            if let Some(flow) = term.info().flow_control {
+                ensure!(
+                    flow.is_valid(),
+                    InvalidFlowControlSnafu {
+                        high: flow.high_watermark,
+                        low: flow.low_watermark,
+                    }
+                );
+            }
+
+            // Spawn up front so the caller hears about failures instead of a silently dead terminal
+            term.set_logging(log)?;
+            let context = term.get_pty()?;
+
+            self.spawn(term, move |term| term.run_loop(bus, context))
+        }
+
+        /// Registers `term` and runs `run` with it on a thread of its own.
+        fn spawn<F>(&mut self, term: Terminal, run: F) -> Result<Terminal, TartError>
+        where
+            F: FnOnce(Terminal) + Send + 'static,
+        {
+            let mut terminals = self.terminals.lock().map_err(|_| {
+                LockPoisonedSnafu {
+                    resource: "terminals",
+                }
+                .build()
+            })?;
+            let mut threads = self.threads.lock().map_err(|_| {
+                LockPoisonedSnafu {
+                    resource: "threads",
+                }
+                .build()
+            })?;
+            let cloned = term.clone();
+            terminals.insert(term.id(), term.clone());
+            threads.insert(term.id(), thread::spawn(move || run(term)));
+            Ok(cloned)
+        }
+
+        /// Runs `f` with terminal `id` while holding the terminals lock.
+        fn with_terminal<R>(
+            &self,
+            id: Uuid,
+            f: impl FnOnce(&Terminal) -> Result<R, TartError>,
+        ) -> Result<R, TartError> {
+            let terminals = self.terminals.lock().map_err(|_| {
+                LockPoisonedSnafu {
+                    resource: "terminals",
                 }
-                return Err("Failed to lock threads mapping");
+                .build()
+            })?;
+            let terminal = terminals.get(&id).context(UnknownTerminalSnafu { id })?;
+            f(terminal)
+        }
+
+        /// Opens a read-only terminal that plays back an asciicast v1/v2 recording.
+        pub fn create_playback(
+            &mut self,
+            bus: EventBus,
+            path: &Path,
+            speed: f64,
+            idle_time_limit: Option<f64>,
+        ) -> Result<Terminal, TartError> {
+            ensure!(PLAYBACK_SPEEDS.contains(&speed), InvalidSpeedSnafu { speed });
+            if let Some(limit) = idle_time_limit {
+                ensure!(limit >= 0.0 && limit.is_finite(), InvalidIdleTimeLimitSnafu { limit });
+            }
+            let mut cast = Cast::load(path)?;
+            if let Some(limit) = idle_time_limit.or(cast.idle_time_limit) {
+                cast.limit_idle_time(limit);
             }
-            return Err("Failed to lock terminals mapping");
+
+            let commands = unbounded::<TerminalMessage>();
+            let title = cast.title.clone().or_else(|| {
+                path.file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+            });
+            let term = Terminal::new(
+                (commands.0.clone(), commands.1.clone()),
+                TerminalOptions {
+                    command: Some(path.display().to_string()),
+                    title,
+                    // Whatever rang or notified was for whoever recorded it
+                    notifications: Some(NotificationPolicy {
+                        muted: true,
+                        ..NotificationPolicy::default()
+                    }),
+                    ..TerminalOptions::default()
+                },
+            );
+            term.prepare_playback(&cast, speed);
+
+            self.spawn(term, move |term| term.run_playback(bus, cast, speed))
+        }
+
+        /// Hands a playback command to a playback terminal, which is all that understands them.
+        pub fn control_playback(&self, id: Uuid, command: TerminalCommand) -> Result<(), TartError> {
+            let info = self.terminal(id).context(UnknownTerminalSnafu { id })?;
+            ensure!(info.kind == TerminalKind::Playback, NotPlaybackSnafu { id });
+            if let TerminalCommand::SetSpeed { speed } = command {
+                ensure!(PLAYBACK_SPEEDS.contains(&speed), InvalidSpeedSnafu { speed });
+            }
+            self.send(id, command)
+        }
+
+        /// Hands a command to a terminal's loop.
+        pub fn send(&self, id: Uuid, command: TerminalCommand) -> Result<(), TartError> {
+            let sender = self.sender(id).context(UnknownTerminalSnafu { id })?;
+            sender
+                .send(TerminalMessage::new(command))
+                .map_err(|_| TerminalClosedSnafu { id }.build())
+        }
+
+        /// Streams a terminal's output, exit and resize events to `channel`.
+        pub fn subscribe(
+            &self,
+            id: Uuid,
+            channel: Channel<FrontendEvent>,
+        ) -> Result<Subscription, TartError> {
+            self.with_terminal(id, |terminal| Ok(terminal.subscribe(channel)))
+        }
+
+        pub fn set_notifications(&self, id: Uuid, policy: NotificationPolicy) -> Result<TerminalInfo, TartError> {
+            self.with_terminal(id, |terminal| Ok(terminal.set_notifications(policy)))
+        }
+
+        /// Marks `id` as the selected terminal, or none of them.
+        pub fn set_focus(&self, id: Option<Uuid>) -> Result<(), TartError> {
+            let terminals = self.terminals.lock().map_err(|_| {
+                LockPoisonedSnafu {
+                    resource: "terminals",
+                }
+                .build()
+            })?;
+            if let Some(id) = id {
+                terminals.get(&id).context(UnknownTerminalSnafu { id })?;
+            }
+            for (terminal_id, terminal) in terminals.iter() {
+                terminal.set_focused(Some(*terminal_id) == id);
+            }
+            Ok(())
+        }
+
+        pub fn set_title(&self, id: Uuid, title: Option<String>) -> Result<TerminalInfo, TartError> {
+            self.with_terminal(id, |terminal| Ok(terminal.set_user_title(title)))
+        }
+
+        pub fn start_recording(&self, id: Uuid, path: &Path, include_input: bool) -> Result<RecordingInfo, TartError> {
+            self.with_terminal(id, |terminal| terminal.start_recording(path, include_input))
+        }
+
+        pub fn stop_recording(&self, id: Uuid) -> Result<Option<RecordingInfo>, TartError> {
+            self.with_terminal(id, |terminal| terminal.stop_recording())
+        }
+
+        pub fn set_logging(&self, id: Uuid, target: Option<&LogTarget>) -> Result<Option<LogTarget>, TartError> {
+            self.with_terminal(id, |terminal| terminal.set_logging(target))
+        }
+
+        pub fn command_history(&self, id: Uuid) -> Result<Vec<CommandRecord>, TartError> {
+            self.with_terminal(id, |terminal| Ok(terminal.command_history()))
+        }
+
+        pub fn snapshot(&self, id: Uuid) -> Result<Snapshot, TartError> {
+            self.with_terminal(id, |terminal| Ok(terminal.snapshot()))
+        }
+
+        pub fn tail(&self, id: Uuid, lines: usize) -> Result<String, TartError> {
+            self.with_terminal(id, |terminal| Ok(terminal.tail(lines)))
+        }
+
+        pub fn unsubscribe(&self, id: Uuid, subscription: Uuid) -> Result<bool, TartError> {
+            self.with_terminal(id, |terminal| Ok(terminal.subscribers.remove(subscription)))
         }
 
         pub fn remove_terminal(&mut self, id: Uuid) -> () {
+            // Take both out first so nothing waits on the locks while the thread winds down
+            let terminal = match self.terminals.lock() {
+                Ok(mut terminals) => terminals.remove(&id),
+                Err(_) => None,
+            };
+            let thread = match self.threads.lock() {
+                Ok(mut threads) => threads.remove(&id),
+                Err(_) => None,
+            };
+            if let Some(terminal) = terminal {
+                let _ = terminal
+                    .commands
+                    .0
+                    .send(TerminalMessage::new(TerminalCommand::Kill));
+            }
+            if let Some(thread) = thread {
+                let _ = thread.join();
+            }
+        }
+
+        /// Joins the thread of a terminal whose process has exited, then removes
+        /// the terminal if its close-on-exit policy says so. Returns whether it was removed.
+        pub fn reap_terminal(&mut self, id: Uuid) -> bool {
+            if let Ok(mut threads) = self.threads.lock() {
+                if let Some(thread) = threads.remove(&id) {
+                    let _ = thread.join();
+                }
+            }
+
             if let Ok(mut terminals) = self.terminals.lock() {
-                if let Ok(mut threads) = self.threads.lock() {
-                    if let Some(sender) = self.sender(id) {
-                        let _ = sender.send(TerminalMessage::new(TerminalCommand::Kill));
-                        if let Some(thread) = threads.remove(&id) {
-                            let _ = thread.join();
-                        }
-                        let _ = terminals.remove(&id);
-                    }
+                let close = match terminals.get(&id).map(|t| t.info()) {
+                    Some(TerminalInfo {
+                        state: TerminalState::Exited { status },
+                        close_on_exit,
+                        ..
+                    }) => close_on_exit.should_close(&status),
+                    _ => false,
+                };
+
+                if close {
+                    let _ = terminals.remove(&id);
                 }
+                close
+            } else {
+                false
             }
         }
 
@@ -356,12 +1610,54 @@ pub mod term {
 
         pub fn list_terminals(&self) -> Vec<TerminalInfo> {
             if let Ok(terminals) = self.terminals.lock() {
-                terminals.values().map(|v| v.info()).collect()
+                let mut list = terminals.values().map(|v| v.info()).collect::<Vec<_>>();
+                list.sort_by_key(|info| info.order);
+                list
             } else {
                 Vec::new()
             }
         }
 
+        /// Sets a terminal's own flush policy, which the config no longer changes from then on.
+        pub fn set_flush_policy(&self, id: Uuid, policy: FlushPolicy) -> Result<(), TartError> {
+            self.with_terminal(id, |terminal| {
+                if let Ok(mut info) = terminal._info.lock() {
+                    info.from_config.flush_policy = false;
+                }
+                terminal
+                    .commands
+                    .0
+                    .send(TerminalMessage::new(TerminalCommand::SetFlushPolicy { policy }))
+                    .map_err(|_| TerminalClosedSnafu { id }.build())
+            })
+        }
+
+        /// Passes the config's new flush policy on to the terminals that took theirs from it.
+        pub fn config_flush_changed(&self, policy: FlushPolicy) {
+            if let Ok(terminals) = self.terminals.lock() {
+                for terminal in terminals.values() {
+                    if terminal.info().from_config.flush_policy {
+                        let _ = terminal
+                            .commands
+                            .0
+                            .send(TerminalMessage::new(TerminalCommand::SetFlushPolicy { policy }));
+                    }
+                }
+            }
+        }
+
+        /// Passes the config's new scrollback limit on to the terminals that took theirs from
+        /// it, including those whose program has exited.
+        pub fn config_scrollback_changed(&self, limit: &ScrollbackConfig) {
+            if let Ok(terminals) = self.terminals.lock() {
+                for terminal in terminals.values() {
+                    if terminal.info().from_config.scrollback {
+                        terminal.set_scrollback(limit.clone());
+                    }
+                }
+            }
+        }
+
         pub fn sender(&self, id: Uuid) -> Option<Sender<TerminalMessage>> {
             if let Ok(terminals) = self.terminals.lock() {
                 if let Some(res) = terminals.get(&id) {
@@ -373,17 +1669,224 @@ pub mod term {
                 None
             }
         }
+    }
 
-        pub fn receiver(&self, id: Uuid) -> Option<Receiver<TerminalMessage>> {
-            if let Ok(terminals) = self.terminals.lock() {
-                if let Some(res) = terminals.get(&id) {
-                    Some(res.results.1.clone())
-                } else {
-                    None
+    #[cfg(test)]
+    mod tests {
+        use tauri::ipc::InvokeBody;
+
+        use super::*;
+
+        /// A channel recording the `bytes` of every output event it receives
+        fn channel() -> (Channel<FrontendEvent>, Arc<Mutex<Vec<usize>>>) {
+            let received = Arc::new(Mutex::new(Vec::new()));
+            let events = received.clone();
+            let channel = Channel::new(move |body| {
+                if let InvokeBody::Json(value) = body {
+                    if let Ok(FrontendEvent::TerminalRead { bytes, .. }) = serde_json::from_value(value) {
+                        events.lock().unwrap().push(bytes);
+                    }
                 }
-            } else {
-                None
+                Ok(())
+            });
+            (channel, received)
+        }
+
+        #[test]
+        fn config_changes_skip_terminals_with_their_own_scrollback() {
+            let manager = TerminalManager::new();
+            let terminal = |from_config| {
+                let terminal = Terminal::new(
+                    unbounded(),
+                    TerminalOptions {
+                        scrollback: Some(ScrollbackConfig::default()),
+                        from_config: ConfigDefaults {
+                            flush_policy: false,
+                            scrollback: from_config,
+                        },
+                        ..Default::default()
+                    },
+                );
+                manager.terminals.lock().unwrap().insert(terminal.id(), terminal.clone());
+                terminal
+            };
+            let (following, own) = (terminal(true), terminal(false));
+
+            let limit = ScrollbackConfig {
+                lines: 10,
+                bytes: 1024,
+            };
+            manager.config_scrollback_changed(&limit);
+            assert_eq!(following.info().scrollback, limit);
+            assert_eq!(own.info().scrollback, ScrollbackConfig::default());
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn login_shell_is_asked_with_dash_l() {
+            let info = Terminal::new(
+                unbounded(),
+                TerminalOptions {
+                    command: Some("/bin/sh".to_string()),
+                    login: Some(true),
+                    ..Default::default()
+                },
+            )
+            .info();
+            let argv = Terminal::command_builder(info).get_argv().clone();
+            assert_eq!(argv, ["/bin/sh", "-l"]);
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn non_executable_command_fails_to_spawn() {
+            let path = std::env::temp_dir().join(format!("tart-test-{}", Uuid::new_v4()));
+            std::fs::write(&path, "#!/bin/sh\n").unwrap();
+            let error = Terminal::check_executable(path.to_str().unwrap()).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::PermissionDenied);
+
+            let missing = path.with_extension("missing");
+            let error = Terminal::check_executable(missing.to_str().unwrap()).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::NotFound);
+            assert!(Terminal::check_executable("/bin/sh").is_ok());
+        }
+
+        #[test]
+        fn flow_control_follows_subscriptions() {
+            let terminal = Terminal::new(
+                unbounded(),
+                TerminalOptions {
+                    flow_control: Some(FlowControl {
+                        high_watermark: 100,
+                        low_watermark: 10,
+                    }),
+                    ..Default::default()
+                },
+            );
+            let output = || terminal.emit_read(OutputEncoding::Utf8, "x".repeat(200), 200);
+
+            // Nobody is there to ack, so nothing may hold the reader back
+            output();
+            assert!(!terminal.backpressure.is_paused());
+
+            let (first, first_events) = channel();
+            let subscription = terminal.subscribe(first).subscription;
+            output();
+            assert!(terminal.backpressure.is_paused());
+            assert_eq!(*first_events.lock().unwrap(), vec![200]);
+
+            assert!(terminal.subscribers.remove(subscription));
+            assert!(!terminal.backpressure.is_paused());
+
+            // A new subscriber starts from a snapshot, which it never acks
+            let (second, second_events) = channel();
+            terminal.subscribe(second);
+            assert!(!terminal.backpressure.is_paused());
+
+            // Only one of two views acks, so output is only counted once
+            let (third, third_events) = channel();
+            terminal.subscribe(third);
+            terminal.emit_read(OutputEncoding::Utf8, "x".repeat(50), 50);
+            assert!(!terminal.backpressure.is_paused());
+            assert_eq!(*second_events.lock().unwrap(), vec![50]);
+            assert_eq!(*third_events.lock().unwrap(), vec![0]);
+            terminal.emit_read(OutputEncoding::Utf8, "x".repeat(50), 50);
+            assert!(terminal.backpressure.is_paused());
+        }
+    }
+
+    /// Benchmarks for the terminal loop, which take a while and print their numbers. Run them
+    /// with `cargo test --release -- --ignored --nocapture bench_`.
+    #[cfg(all(test, target_os = "linux"))]
+    mod benches {
+        use std::{fs, io::BufWriter};
+
+        use super::*;
+        use crate::common::tartd::Clients;
+
+        /// The user and system CPU time this process has used so far. Children aren't counted,
+        /// so the shells and `cat` don't skew it.
+        fn cpu_time() -> Duration {
+            let stat = fs::read_to_string("/proc/self/stat").unwrap();
+
+            // The command name may contain spaces, so count from the parenthesis closing it
+            let fields = stat[stat.rfind(')').unwrap() + 2..].split_whitespace().collect::<Vec<_>>();
+            let ticks = fields[11].parse::<u64>().unwrap() + fields[12].parse::<u64>().unwrap();
+
+            // USER_HZ is 100 on every architecture Linux supports
+            Duration::from_millis(ticks * 10)
+        }
+
+        fn bus() -> (EventBus, Receiver<BackendEvent>) {
+            let (backend, events) = unbounded();
+            let bus = EventBus::Daemon {
+                clients: Clients::default(),
+                backend,
+            };
+            (bus, events)
+        }
+
+        fn options(command: &str, args: &[&str]) -> TerminalOptions {
+            TerminalOptions {
+                command: Some(command.to_string()),
+                args: Some(args.iter().map(|arg| arg.to_string()).collect()),
+                shell_integration: Some(false),
+                ..Default::default()
+            }
+        }
+
+        #[test]
+        #[ignore]
+        fn bench_idle_cpu_with_20_terminals() {
+            let (bus, _events) = bus();
+            let mut terminals = TerminalManager::new();
+            let ids = (0..20)
+                .map(|_| terminals.create_terminal(bus.clone(), options("sh", &[])).unwrap().id())
+                .collect::<Vec<_>>();
+
+            // Let the shells start and print their prompts before measuring
+            thread::sleep(Duration::from_secs(2));
+            let (started, used) = (Instant::now(), cpu_time());
+            thread::sleep(Duration::from_secs(10));
+            let share = (cpu_time() - used).as_secs_f64() / started.elapsed().as_secs_f64();
+            println!("20 idle terminals used {:.2}% of a core", share * 100.0);
+
+            for id in ids {
+                terminals.remove_terminal(id);
+            }
+            assert!(share < 0.01, "idle terminals should be asleep, not polling");
+        }
+
+        #[test]
+        #[ignore]
+        fn bench_cat_throughput() {
+            const SIZE: usize = 500 * 1024 * 1024;
+            let path = std::env::temp_dir().join(format!("tart-bench-{}.txt", Uuid::new_v4()));
+            let mut file = BufWriter::new(fs::File::create(&path).unwrap());
+            let line = "The quick brown fox jumps over the lazy dog, 0123456789 äöü 中文 😀\n";
+            for _ in 0..SIZE / line.len() {
+                file.write_all(line.as_bytes()).unwrap();
             }
+            drop(file);
+
+            let (bus, events) = bus();
+            let mut terminals = TerminalManager::new();
+            let started = Instant::now();
+            let id = terminals
+                .create_terminal(bus, options("cat", &[&path.to_string_lossy()]))
+                .unwrap()
+                .id();
+            let exited = events.recv_timeout(Duration::from_secs(600));
+            let elapsed = started.elapsed();
+            let _ = fs::remove_file(&path);
+
+            assert!(matches!(exited, Ok(BackendEvent::TerminalExited { id: exited, .. }) if exited == id));
+            println!(
+                "cat of {} MB took {:.2}s, {:.0} MB/s",
+                SIZE / 1024 / 1024,
+                elapsed.as_secs_f64(),
+                SIZE as f64 / 1024.0 / 1024.0 / elapsed.as_secs_f64()
+            );
         }
     }
 }
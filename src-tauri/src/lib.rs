@@ -1,5 +1,12 @@
-use common::app_state::{AppCommand, AppState, CommandResult};
-use tauri::Manager;
+use common::app_state::{AppCommand, CommandResult, FrontendEvent};
+use common::command_line::{self, Launch, Outcome};
+use common::error::{CommandFailedSnafu, ErrorPayload};
+use common::remote_control::ControlSocket;
+use common::single_instance::InstanceLock;
+use common::tartd::{self, Backend};
+use common::term::Subscription;
+use tauri::{ipc::Channel, Manager, RunEvent};
+use uuid::Uuid;
 
 mod common;
 
@@ -7,23 +14,101 @@ mod common;
 async fn execute_command(
     app: tauri::AppHandle,
     command: AppCommand
-) -> Result<CommandResult, String> {
-    let state = app.state::<AppState>();
-    Ok(state.run_command(&app.clone(), command).await)
+) -> Result<CommandResult, ErrorPayload> {
+    blocking(move || {
+        let backend = app.state::<Backend>();
+        backend.run_command(&app, command).map_err(|e| e.payload())
+    }).await
+}
+
+#[tauri::command]
+async fn subscribe(
+    app: tauri::AppHandle,
+    id: Uuid,
+    channel: Channel<FrontendEvent>
+) -> Result<Subscription, ErrorPayload> {
+    blocking(move || {
+        let backend = app.state::<Backend>();
+        backend.subscribe(id, channel)
+    }).await
+}
+
+/// Runs a command's handler where it may block, e.g. waiting on `tartd` to reply, without
+/// holding up the async runtime's workers.
+async fn blocking<T: Send + 'static>(
+    handler: impl FnOnce() -> Result<T, ErrorPayload> + Send + 'static
+) -> Result<T, ErrorPayload> {
+    tauri::async_runtime::spawn_blocking(handler).await.unwrap_or_else(|e| {
+        Err(CommandFailedSnafu { reason: e.to_string() }.build().payload())
+    })
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    open(Launch::default(), None)
+}
+
+fn open(launch: Launch, lock: Option<InstanceLock>) {
     tauri::Builder::default()
-        .setup(|app| {
-            app.manage(AppState::new());
+        .plugin(tauri_plugin_notification::init())
+        .setup(move |app| {
+            let backend = Backend::start(app.handle());
+            if let Some(command) = launch.terminal {
+                match backend.run_command(app.handle(), *command) {
+                    Ok(result) => {
+                        if let Err(e) = result.result() {
+                            println!("Failed to open a terminal: {}", e);
+                        }
+                    }
+                    Err(e) => println!("Failed to open a terminal: {}", e),
+                }
+            }
+            app.manage(backend);
 
-            let state = app.state::<AppState>();
-            state.run_in_background(app.handle());
+            // A separate instance leaves the CLI and later launches to the one they'd reach anyway
+            if !launch.new_instance {
+                match ControlSocket::listen(app.handle()) {
+                    Ok(socket) => {
+                        app.manage(socket);
+                    }
+                    Err(e) => println!("{}, the tart command won't reach this window", e),
+                }
+            }
+            if let Some(lock) = lock {
+                app.manage(lock);
+            }
 
             Ok(())
         })
-        .invoke_handler(tauri::generate_handler![execute_command])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .invoke_handler(tauri::generate_handler![execute_command, subscribe])
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| {
+            if let RunEvent::Exit = event {
+                app.state::<Backend>().save_session();
+                if let Some(socket) = app.try_state::<ControlSocket>() {
+                    socket.close();
+                }
+            }
+        });
+}
+
+/// Runs `tartd`, which keeps terminals running while no GUI is open.
+pub fn run_daemon() {
+    if let Err(e) = tartd::serve(&tartd::socket_path()) {
+        println!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Runs `tart` with the given arguments: a subcommand against the running instance, a launch
+/// handed over to it, or the window itself. Returns the exit code.
+pub fn run_cli(args: &[String]) -> i32 {
+    match command_line::run(args) {
+        Outcome::Exit(code) => code,
+        Outcome::Open(launch, lock) => {
+            open(launch, lock);
+            0
+        }
+    }
 }
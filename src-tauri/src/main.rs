@@ -2,5 +2,6 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
-    tart_lib::run()
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    std::process::exit(tart_lib::run_cli(&args))
 }
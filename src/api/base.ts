@@ -1,5 +1,6 @@
 import { invoke } from "@tauri-apps/api/core";
 import { camelCase, upperFirst } from "lodash";
+import { TartError } from "./types";
 
 export type Command<TCommand extends string, TData extends object = {}> = {
     type: TCommand;
@@ -26,7 +27,7 @@ type BareCommandResult<
 
 export async function execute<
     TResult = any,
-    TError = any,
+    TError = TartError,
     TCommand extends string = any,
     TData extends object = any
 >(
@@ -37,9 +38,18 @@ export async function execute<
         type: upperFirst(camelCase(command)),
         ...(data ?? {}),
     };
-    const result = await invoke<
-        BareCommandResult<Command<TCommand, TData>, TResult, TError>
-    >("execute_command", { command: cmd });
+    let result: BareCommandResult<Command<TCommand, TData>, TResult, TError>;
+    try {
+        result = await invoke("execute_command", { command: cmd });
+    } catch (error) {
+        // The command didn't get to run, e.g. because tartd went away
+        return {
+            id: "",
+            command: cmd as any,
+            success: false,
+            data: error as TError,
+        };
+    }
     if (result.result.Ok !== undefined) {
         return {
             id: result.id,
@@ -1,7 +1,17 @@
+import { Channel, invoke } from "@tauri-apps/api/core";
 import { listen, UnlistenFn } from "@tauri-apps/api/event";
 import { omit } from "lodash";
 import { useCallback, useEffect, useState } from "react";
-import { Terminal } from "./types";
+import {
+    Profile,
+    Session,
+    Snapshot,
+    Subscription,
+    TartConfig,
+    TartError,
+    Terminal,
+    TerminalStreamEvent,
+} from "./types";
 import { execute } from "./base";
 
 export function useAppEvent<T extends object = {}>(
@@ -19,6 +29,55 @@ export function useAppEvent<T extends object = {}>(
     }, [type, callback]);
 }
 
+/**
+ * Streams one terminal's output, exit and resize events, unsubscribing on unmount.
+ * `onSnapshot` receives the terminal's contents up to the first streamed event.
+ */
+export function useTerminalStream(
+    id: string,
+    callback: (event: TerminalStreamEvent) => void,
+    onSnapshot?: (snapshot: Snapshot) => void
+) {
+    useEffect(() => {
+        let active = true;
+        let subscription: string | null = null;
+        // Events can arrive before the snapshot they follow, so hold them until it's applied
+        let pending: TerminalStreamEvent[] | null = [];
+        const channel = new Channel<TerminalStreamEvent>();
+        channel.onmessage = (event) => {
+            if (!active) {
+                return;
+            }
+            if (pending) {
+                pending.push(event);
+            } else {
+                callback(event);
+            }
+        };
+
+        invoke<Subscription>("subscribe", { id, channel }).then((result) => {
+            if (active) {
+                subscription = result.subscription;
+                onSnapshot?.(result.snapshot);
+                pending?.forEach(callback);
+                pending = null;
+            } else {
+                execute("unsubscribe", {
+                    id,
+                    subscription: result.subscription,
+                });
+            }
+        });
+
+        return () => {
+            active = false;
+            if (subscription) {
+                execute("unsubscribe", { id, subscription });
+            }
+        };
+    }, [id, callback, onSnapshot]);
+}
+
 export function useTerminals(): Terminal[] {
     const [terminals, setTerminals] = useState<Terminal[]>([]);
     const onEvent = useCallback(
@@ -36,6 +95,9 @@ export function useTerminals(): Terminal[] {
     useAppEvent("TerminalCreated", onEvent);
     useAppEvent("TerminalResized", onEvent);
     useAppEvent("TerminalRemoved", onEvent);
+    useAppEvent("TerminalExited", onEvent);
+    useAppEvent("TerminalTitleChanged", onEvent);
+    useAppEvent("TerminalCwdChanged", onEvent);
 
     useEffect(() => {
         onEvent();
@@ -43,3 +105,134 @@ export function useTerminals(): Terminal[] {
 
     return terminals;
 }
+
+/** IDs of the terminals that asked for attention since they were last selected. */
+export function useAttention(current: string | null): string[] {
+    const [attention, setAttention] = useState<string[]>([]);
+    const onAttention = useCallback(
+        ({ id }: { id: string }) =>
+            setAttention((ids) =>
+                ids.includes(id) || id === current ? ids : [...ids, id]
+            ),
+        [current, setAttention]
+    );
+
+    useAppEvent("Attention", onAttention);
+
+    useEffect(() => {
+        execute("set_focus", { id: current });
+        setAttention((ids) => ids.filter((id) => id !== current));
+    }, [current, setAttention]);
+
+    return attention;
+}
+
+/**
+ * Selects the terminals the `tart` command asks to show, waiting for ones it just
+ * opened to be listed first.
+ */
+export function useShowRequests(
+    terminals: Terminal[],
+    select: (terminal: Terminal) => void
+) {
+    const [requested, setRequested] = useState<string | null>(null);
+
+    useEffect(() => {
+        let unlisten: UnlistenFn = () => {};
+        listen<string>("tart://show", (evt) => setRequested(evt.payload)).then(
+            (f) => (unlisten = f)
+        );
+        return () => unlisten();
+    }, [setRequested]);
+
+    useEffect(() => {
+        const terminal = terminals.find(
+            (terminal) => terminal.id === requested
+        );
+        if (terminal) {
+            select(terminal);
+            setRequested(null);
+        }
+    }, [terminals, requested, select, setRequested]);
+}
+
+/**
+ * The saved profiles, why they couldn't be loaded if `profiles.toml` is broken, and a
+ * callback listing them again. Profiles can't be changed until the file loads again.
+ */
+export function useProfiles(): [Profile[], TartError | null, () => void] {
+    const [profiles, setProfiles] = useState<Profile[]>([]);
+    const [error, setError] = useState<TartError | null>(null);
+    const onEvent = useCallback(
+        () =>
+            execute<Profile[]>("list_profiles").then((result) => {
+                if (result.success) {
+                    setProfiles(result.data);
+                    setError(null);
+                } else {
+                    setProfiles([]);
+                    setError(result.data);
+                }
+            }),
+        [setProfiles, setError]
+    );
+
+    useAppEvent("ProfilesChanged", onEvent);
+
+    useEffect(() => {
+        onEvent();
+    }, [onEvent]);
+
+    return [profiles, error, onEvent];
+}
+
+export function useConfig(): TartConfig | null {
+    const [config, setConfig] = useState<TartConfig | null>(null);
+    const onChanged = useCallback(
+        ({ config }: { config: TartConfig }) => setConfig(config),
+        [setConfig]
+    );
+
+    useAppEvent("ConfigChanged", onChanged);
+
+    useEffect(() => {
+        execute<TartConfig>("get_config").then((result) => {
+            if (result.success) {
+                setConfig(result.data);
+            }
+        });
+    }, [setConfig]);
+
+    return config;
+}
+
+/**
+ * The session saved when tart last exited, until it's restored or dismissed.
+ * Only looked up once, so it isn't offered again after new terminals are opened.
+ */
+export function useSavedSession(): [
+    Session | null,
+    () => Promise<void>,
+    () => Promise<void>
+] {
+    const [session, setSession] = useState<Session | null>(null);
+
+    useEffect(() => {
+        execute<Session | null>("get_saved_session").then((result) => {
+            if (result.success && result.data?.terminals.length) {
+                setSession(result.data);
+            }
+        });
+    }, [setSession]);
+
+    const restore = useCallback(async () => {
+        setSession(null);
+        await execute("restore_session");
+    }, [setSession]);
+    const dismiss = useCallback(async () => {
+        setSession(null);
+        await execute("discard_session");
+    }, [setSession]);
+
+    return [session, restore, dismiss];
+}
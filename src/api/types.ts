@@ -5,10 +5,262 @@ export type PtySize = {
     pixel_height: number;
 };
 
+export type ExitInfo = {
+    code: number;
+    signal: string | null;
+    success: boolean;
+};
+
+export type TerminalState =
+    | { type: "Running" }
+    | { type: "Exited"; status: ExitInfo };
+
+export type CloseOnExit = "Always" | "OnSuccess" | "Never";
+
+export type OutputEncoding = "Utf8" | "Base64";
+
+export type FlushPolicy = {
+    interval_ms: number;
+    max_bytes: number;
+};
+
+export type FlowControl = {
+    high_watermark: number;
+    low_watermark: number;
+};
+
+export type TerminalKind = "Pty" | "Playback";
+
+export type PlaybackStatus = {
+    position: number;
+    duration: number;
+    speed: number;
+    paused: boolean;
+};
+
 export type Terminal = {
     id: string;
+    order: number;
+    kind: TerminalKind;
     command: string;
     args: string[] | null;
+    login: boolean;
     title: string | null;
+    user_title: string | null;
+    program_title: string | null;
+    cwd: string | null;
+    current_dir: string | null;
+    env: { [key: string]: string };
+    env_remove: string[];
+    clear_env: boolean;
+    profile: string | null;
+    state: TerminalState;
+    close_on_exit: CloseOnExit;
+    encoding: OutputEncoding;
+    flush_policy: FlushPolicy;
+    flow_control: FlowControl | null;
+    scrollback: { lines: number; bytes: number };
+    shell_integration: boolean;
+    notifications: NotificationPolicy;
+    /** Settings taken from the config, which follow it when it's reloaded */
+    from_config: { flush_policy: boolean; scrollback: boolean };
+    focused: boolean;
+    recording: RecordingInfo | null;
+    playback: PlaybackStatus | null;
+    log: LogTarget | null;
     size: PtySize;
 };
+
+export type LogFormat = "Raw" | "Stripped" | "Timestamped";
+
+export type LogTarget = {
+    path: string;
+    format: LogFormat;
+    max_bytes: number;
+    keep: number;
+};
+
+/** Terminals saved when tart last exited, in tab order. */
+export type Session = {
+    saved_at: number;
+    terminals: { info: Terminal; contents: string | null }[];
+};
+
+export type RestoredSession = {
+    terminals: Terminal[];
+    failed: TartError[];
+};
+
+export type RecordingInfo = {
+    path: string;
+    include_input: boolean;
+    started_at: number;
+};
+
+export type NotificationPolicy = {
+    threshold: number;
+    only_on_failure: boolean;
+    muted: boolean;
+};
+
+/** Why a terminal asked for attention, `duration` is in milliseconds. */
+export type AttentionReason =
+    | {
+          type: "CommandFinished";
+          command: string;
+          exit_code: number | null;
+          duration: number;
+      }
+    | { type: "Notification"; title: string | null; body: string }
+    | { type: "Bell" };
+
+/** A command run from a prompt, as reported by shell integration (OSC 133). */
+export type CommandRecord = {
+    prompt_line: number;
+    command: string | null;
+    started_at: number | null;
+    finished_at: number | null;
+    exit_code: number | null;
+    output_start: number | null;
+    output_end: number | null;
+};
+
+export type Snapshot = {
+    rows: number;
+    cols: number;
+    scrollback: number;
+    cursor: { row: number; col: number; pending_wrap: boolean };
+    modes: {
+        autowrap: boolean;
+        origin: boolean;
+        insert: boolean;
+        cursor_visible: boolean;
+        application_cursor: boolean;
+        bracketed_paste: boolean;
+        alternate_screen: boolean;
+    };
+    data: string;
+};
+
+export type Subscription = {
+    subscription: string;
+    snapshot: Snapshot;
+};
+
+/** Events streamed to a single terminal's subscribers. */
+export type TerminalStreamEvent =
+    | {
+          type: "TerminalRead";
+          id: string;
+          data: string;
+          encoding: OutputEncoding;
+          bytes: number;
+      }
+    | { type: "TerminalExited"; id: string; status: ExitInfo }
+    | { type: "TerminalResized"; id: string; size: PtySize };
+
+export type TerminalOptions = {
+    command?: string | null;
+    args?: string[] | null;
+    login?: boolean | null;
+    title?: string | null;
+    cwd?: string | null;
+    env?: { [key: string]: string } | null;
+    env_remove?: string[] | null;
+    clear_env?: boolean | null;
+    close_on_exit?: CloseOnExit | null;
+    encoding?: OutputEncoding | null;
+    flow_control?: Partial<FlowControl> | null;
+    scrollback?: { lines: number; bytes: number } | null;
+    shell_integration?: boolean | null;
+    notifications?: Partial<NotificationPolicy> | null;
+    log?: LogTarget | null;
+    profile?: string | null;
+};
+
+export type ShellInfo = {
+    path: string;
+    name: string;
+};
+
+export type Profile = {
+    name: string;
+    command?: string | null;
+    args?: string[] | null;
+    login?: boolean | null;
+    cwd?: string | null;
+    env?: { [key: string]: string } | null;
+    env_remove?: string[] | null;
+    clear_env?: boolean | null;
+    title?: string | null;
+    close_on_exit?: CloseOnExit | null;
+    shell_integration?: boolean | null;
+    notifications?: Partial<NotificationPolicy> | null;
+};
+
+export type TartConfig = {
+    font: { family: string; size: number };
+    colors: {
+        foreground: string | null;
+        background: string | null;
+        cursor: string | null;
+        selection: string | null;
+        palette: string[];
+    };
+    default_profile: string | null;
+    scrollback: { lines: number; bytes: number };
+    keybindings: { [action: string]: string };
+    flush: FlushPolicy;
+    shell_integration: boolean;
+    notifications: NotificationPolicy;
+    logging: {
+        enabled: boolean;
+        directory: string;
+        format: LogFormat;
+        max_bytes: number;
+        keep: number;
+    };
+    session: { save: boolean; scrollback_lines: number };
+};
+
+export type ConfigError = {
+    message: string;
+    line: number | null;
+    column: number | null;
+};
+
+export type TartErrorKind =
+    | "pty_open"
+    | "spawn"
+    | "pty_handles"
+    | "write"
+    | "resize"
+    | "unknown_terminal"
+    | "terminal_closed"
+    | "lock_poisoned"
+    | "serialization"
+    | "unknown_profile"
+    | "profile_exists"
+    | "invalid_flow_control"
+    | "read_file"
+    | "write_file"
+    | "parse_toml"
+    | "profiles_unreadable"
+    | "serialize_toml"
+    | "parse_cast"
+    | "not_playback"
+    | "invalid_speed"
+    | "invalid_idle_time_limit"
+    | "parse_session"
+    | "socket"
+    | "already_running"
+    | "daemon_disconnected"
+    | "not_running"
+    | "invalid_arguments"
+    | "command_failed";
+
+export type TartError = {
+    kind: TartErrorKind;
+    message: string;
+    details: { [key: string]: any };
+};
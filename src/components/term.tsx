@@ -3,9 +3,14 @@ import { FitAddon } from "@xterm/addon-fit";
 import { SearchAddon } from "@xterm/addon-search";
 import { WebglAddon } from "@xterm/addon-webgl";
 import { SerializeAddon } from "@xterm/addon-serialize";
-import { PtySize, Terminal } from "../api/types";
+import {
+    PtySize,
+    Snapshot,
+    Terminal,
+    TerminalStreamEvent,
+} from "../api/types";
 import { useCallback, useEffect, useRef, useState } from "react";
-import { useAppEvent } from "../api/hooks";
+import { useConfig, useTerminalStream } from "../api/hooks";
 import { execute } from "../api/base";
 
 type TerminalContext = {
@@ -71,6 +76,56 @@ export function TerminalPanel({ terminal }: { terminal: Terminal }) {
         content: "",
     });
 
+    const config = useConfig();
+    useEffect(() => {
+        if (!config) {
+            return;
+        }
+        const [
+            black,
+            red,
+            green,
+            yellow,
+            blue,
+            magenta,
+            cyan,
+            white,
+            brightBlack,
+            brightRed,
+            brightGreen,
+            brightYellow,
+            brightBlue,
+            brightMagenta,
+            brightCyan,
+            brightWhite,
+        ] = config.colors.palette;
+        term.terminal.options.fontFamily = config.font.family;
+        term.terminal.options.fontSize = config.font.size;
+        term.terminal.options.scrollback = config.scrollback.lines;
+        term.terminal.options.theme = {
+            foreground: config.colors.foreground ?? undefined,
+            background: config.colors.background ?? undefined,
+            cursor: config.colors.cursor ?? undefined,
+            selectionBackground: config.colors.selection ?? undefined,
+            black,
+            red,
+            green,
+            yellow,
+            blue,
+            magenta,
+            cyan,
+            white,
+            brightBlack,
+            brightRed,
+            brightGreen,
+            brightYellow,
+            brightBlue,
+            brightMagenta,
+            brightCyan,
+            brightWhite,
+        };
+    }, [config, term.terminal]);
+
     useEffect(() => {
         if (terminalRef.current) {
             term.terminal.open(terminalRef.current);
@@ -87,13 +142,38 @@ export function TerminalPanel({ terminal }: { terminal: Terminal }) {
         setTerm(createTerminal(terminalState.size, terminalState.content));
     }, [terminal.id, setTerm, setTerminalState]);
 
-    const onRead = useCallback(
-        ({ id, data }: { id: string; data: string }) => {
-            if (id === terminal.id) {
-                term.terminal.write(data);
+    const onStream = useCallback(
+        (event: TerminalStreamEvent) => {
+            // A recording plays back at the size it was recorded at
+            if (
+                event.type === "TerminalResized" &&
+                terminal.kind === "Playback"
+            ) {
+                term.terminal.resize(event.size.cols, event.size.rows);
+                return;
+            }
+            if (event.type !== "TerminalRead") {
+                return;
+            }
+            // Another view of this terminal may be the one acknowledging its output
+            const acknowledge = () => {
+                if (terminal.flow_control && event.bytes > 0) {
+                    execute("ack_output", {
+                        id: terminal.id,
+                        bytes: event.bytes,
+                    });
+                }
+            };
+            if (event.encoding === "Base64") {
+                term.terminal.write(
+                    Uint8Array.from(atob(event.data), (c) => c.charCodeAt(0)),
+                    acknowledge
+                );
+            } else {
+                term.terminal.write(event.data, acknowledge);
             }
         },
-        [term.terminal, terminal.id]
+        [term.terminal, terminal.id, terminal.kind, terminal.flow_control]
     );
 
     useEffect(() => {
@@ -104,15 +184,30 @@ export function TerminalPanel({ terminal }: { terminal: Terminal }) {
         return dispose.dispose;
     }, [terminal.id, term.terminal]);
 
-    term.addons.fit.fit();
+    // Playback keeps the recording's size rather than fitting the panel
+    const fits = terminal.kind !== "Playback";
+    if (fits) {
+        term.addons.fit.fit();
+    }
     console.log(term.addons.fit.proposeDimensions());
 
     useEffect(() => {
         console.log(term.addons.fit.proposeDimensions());
-        term.addons.fit.fit();
+        if (fits) {
+            term.addons.fit.fit();
+        }
     });
 
-    useAppEvent<{ id: string; data: string }>("TerminalRead", onRead);
+    const onSnapshot = useCallback(
+        (snapshot: Snapshot) => {
+            term.terminal.reset();
+            term.terminal.resize(snapshot.cols, snapshot.rows);
+            term.terminal.write(snapshot.data);
+        },
+        [term.terminal]
+    );
+
+    useTerminalStream(terminal.id, onStream, onSnapshot);
 
     return <div className="terminal-container" ref={terminalRef}></div>;
 }
@@ -1,14 +1,29 @@
 import {
     ActionIcon,
+    Alert,
     Box,
+    Indicator,
     Button,
     Divider,
     Group,
+    Menu,
     Stack,
     Text,
 } from "@mantine/core";
-import { IconCookieFilled, IconPlus, IconTerminal } from "@tabler/icons-react";
-import { useTerminals } from "../api/hooks";
+import {
+    IconAlertTriangle,
+    IconCookieFilled,
+    IconHistory,
+    IconPlus,
+    IconTerminal,
+} from "@tabler/icons-react";
+import {
+    useAttention,
+    useProfiles,
+    useSavedSession,
+    useShowRequests,
+    useTerminals,
+} from "../api/hooks";
 import { useState } from "react";
 import { Terminal } from "../api/types";
 import { execute } from "../api/base";
@@ -16,7 +31,15 @@ import { TerminalView } from "./Terminal";
 
 export function Layout() {
     const terminals = useTerminals();
+    const [profiles, profilesError, refreshProfiles] = useProfiles();
     const [current, setCurrent] = useState<Terminal | null>(null);
+    const attention = useAttention(current?.id ?? null);
+    const [session, restoreSession, dismissSession] = useSavedSession();
+    useShowRequests(terminals, setCurrent);
+    // New terminals open wherever the focused one's shell currently is
+    const here =
+        terminals.find((terminal) => terminal.id === current?.id)
+            ?.current_dir ?? null;
 
     return (
         <Stack className="layout" gap={0}>
@@ -25,33 +48,105 @@ export function Layout() {
                 <Divider orientation="vertical" />
                 <Group gap="xs" className="tabs" wrap="nowrap">
                     {terminals.map((terminal) => (
-                        <Button
+                        <Indicator
                             key={terminal.id}
-                            onClick={() => setCurrent(terminal)}
-                            variant={
-                                terminal.id === current?.id ? "filled" : "light"
-                            }
-                            leftSection={<IconTerminal size={20} />}
+                            disabled={!attention.includes(terminal.id)}
+                            processing
                         >
-                            {terminal.title ?? terminal.command}
-                        </Button>
+                            <Button
+                                onClick={() => setCurrent(terminal)}
+                                variant={
+                                    terminal.id === current?.id
+                                        ? "filled"
+                                        : "light"
+                                }
+                                leftSection={<IconTerminal size={20} />}
+                            >
+                                {terminal.user_title ??
+                                    terminal.program_title ??
+                                    terminal.title ??
+                                    terminal.command}
+                            </Button>
+                        </Indicator>
                     ))}
                 </Group>
-                <ActionIcon
-                    size="md"
-                    variant="subtle"
-                    onClick={() =>
-                        execute("create_terminal", {
-                            command: "zsh",
-                            args: null,
-                            title: "Shell",
-                        })
-                    }
-                >
-                    <IconPlus size={20} />
-                </ActionIcon>
+                <Menu position="bottom-end" onOpen={refreshProfiles}>
+                    <Menu.Target>
+                        <ActionIcon size="md" variant="subtle">
+                            <IconPlus size={20} />
+                        </ActionIcon>
+                    </Menu.Target>
+                    <Menu.Dropdown>
+                        {/* Always there, even without profiles to pick from */}
+                        <Menu.Item
+                            leftSection={<IconTerminal size={16} />}
+                            onClick={() =>
+                                execute("create_terminal", {
+                                    options: {
+                                        flow_control: {},
+                                        cwd: here,
+                                    },
+                                })
+                            }
+                        >
+                            Default shell
+                        </Menu.Item>
+                        {profiles.length > 0 && <Menu.Divider />}
+                        {profiles.map((profile) => (
+                            <Menu.Item
+                                key={profile.name}
+                                leftSection={<IconTerminal size={16} />}
+                                onClick={() =>
+                                    execute("create_terminal_from_profile", {
+                                        profile: profile.name,
+                                        overrides: {
+                                            flow_control: {},
+                                            cwd: here,
+                                        },
+                                    })
+                                }
+                            >
+                                {profile.name}
+                            </Menu.Item>
+                        ))}
+                    </Menu.Dropdown>
+                </Menu>
             </Group>
             <Divider />
+            {profilesError && (
+                <Alert
+                    icon={<IconAlertTriangle size={20} />}
+                    title="Your profiles couldn't be loaded"
+                    color="red"
+                    radius={0}
+                >
+                    {profilesError.message}
+                </Alert>
+            )}
+            {session && (
+                <Alert
+                    icon={<IconHistory size={20} />}
+                    title={`Restore ${session.terminals.length} terminal${
+                        session.terminals.length === 1 ? "" : "s"
+                    } from your last session?`}
+                    withCloseButton
+                    onClose={dismissSession}
+                    radius={0}
+                >
+                    <Group gap="xs">
+                        <Button size="xs" onClick={restoreSession}>
+                            Restore
+                        </Button>
+                        <Button
+                            size="xs"
+                            variant="subtle"
+                            onClick={dismissSession}
+                        >
+                            Dismiss
+                        </Button>
+                    </Group>
+                </Alert>
+            )}
             <Box className="content">
                 {terminals.map((terminal) => (
                     <TerminalView